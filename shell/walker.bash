@@ -0,0 +1,12 @@
+# cd-on-exit for bash. Add `source /path/to/walker.bash` to ~/.bashrc and
+# run `wcd` instead of `walker` to end up in the last directory you visited.
+wcd() {
+    local tmp dir
+    tmp="$(mktemp)" || return
+    walker --choosedir="$tmp" "$@"
+    dir="$(cat "$tmp")"
+    rm -f "$tmp"
+    if [ -d "$dir" ] && [ "$dir" != "$PWD" ]; then
+        cd -- "$dir" || return
+    fi
+}
@@ -0,0 +1,12 @@
+# cd-on-exit for fish. Save as ~/.config/fish/functions/wcd.fish (or source
+# it from config.fish) and run `wcd` instead of `walker` to end up in the
+# last directory you visited.
+function wcd --wraps walker --description 'walker with cd-on-exit'
+    set -l tmp (mktemp); or return
+    walker --choosedir=$tmp $argv
+    set -l dir (cat $tmp)
+    rm -f $tmp
+    if test -d "$dir"; and test "$dir" != "$PWD"
+        cd -- $dir
+    end
+end
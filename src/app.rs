@@ -1,16 +1,79 @@
-use std::{os::unix::prelude::PermissionsExt, path::Path};
+use std::{
+    collections::HashSet,
+    fs::{self, Metadata},
+    io::Write,
+    os::unix::{
+        fs::OpenOptionsExt,
+        prelude::{FileTypeExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
 
+use crate::bookmarks::Bookmarks;
+use crate::command::{self, Command, Setting};
+use crate::compare::{DirComparison, FileDiff, SyncDirection};
+use crate::config::Config;
+use crate::du::DirSizes;
+use crate::frecency::Frecency;
+use crate::git::{self, GitStatus, GitStatuses};
+use crate::listing::{self, ListOptions};
+use crate::ops::{self, LinkKind};
+use crate::pattern::RenamePattern;
+use crate::perms::{self, ChmodDialog};
+use crate::rename;
+use crate::shell::{self, Capture, Placeholders};
+use crate::theme::Theme;
 use crate::view::WalkerView;
-use anyhow::Result;
+use crate::watch::Watcher;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Local, TimeZone};
+use tempfile::TempDir;
 use tui::widgets::TableState;
 use tui_input::Input;
 use walkdir::WalkDir;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum EditingKind {
     Rename,
     Copy,
+    Move,
+    RenameBookmark,
+    Jump,
+    Filter,
+    ConfirmDelete,
+    ConfirmDiscard,
+    Command,
+    Shell,
+    ShellCapture,
+    NewFile,
+    NewDirectory,
+    PatternRename,
+    Chown,
+}
+
+impl EditingKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            EditingKind::Rename => "Rename",
+            EditingKind::Copy => "Copy to",
+            EditingKind::Move => "Move to",
+            EditingKind::RenameBookmark => "Rename bookmark",
+            EditingKind::Jump => "Jump to",
+            EditingKind::Filter => "Filter (empty to clear)",
+            EditingKind::ConfirmDelete => "Delete the marked or selected entries? (y/N)",
+            EditingKind::ConfirmDiscard => "Discard the unstaged changes? (y/N)",
+            EditingKind::Command => ":",
+            EditingKind::Shell => "Shell command (%s selected, %f marked, %d/%D panel dirs)",
+            EditingKind::ShellCapture => "Shell command, output in a popup (%s, %f, %d, %D)",
+            EditingKind::NewFile => "New file (end with / for a directory)",
+            EditingKind::NewDirectory => "New directory",
+            EditingKind::Chown => "Owner, owner:group or :group (Tab completes)",
+            EditingKind::PatternRename => {
+                "Rename with a template ({n:03}_{stem}{.ext}) or s/regex/replacement/"
+            }
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -26,12 +89,48 @@ pub enum PanelKind {
 }
 
 impl InputMode {
-    pub fn is_copy(&self) -> bool {
-        matches!(*self, InputMode::Editing(EditingKind::Copy))
+    pub fn is_transfer(&self) -> bool {
+        matches!(
+            *self,
+            InputMode::Editing(EditingKind::Copy) | InputMode::Editing(EditingKind::Move)
+        )
     }
+}
 
-    pub fn is_renaming(&self) -> bool {
-        matches!(*self, InputMode::Editing(EditingKind::Rename))
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ItemKind {
+    File,
+    Directory,
+    Symlink,
+    BrokenSymlink,
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+}
+
+impl ItemKind {
+    fn from_metadata(meta: &Metadata, path: &Path) -> Self {
+        let file_type = meta.file_type();
+        if file_type.is_symlink() {
+            if path.exists() {
+                ItemKind::Symlink
+            } else {
+                ItemKind::BrokenSymlink
+            }
+        } else if file_type.is_dir() {
+            ItemKind::Directory
+        } else if file_type.is_socket() {
+            ItemKind::Socket
+        } else if file_type.is_fifo() {
+            ItemKind::Fifo
+        } else if file_type.is_block_device() {
+            ItemKind::BlockDevice
+        } else if file_type.is_char_device() {
+            ItemKind::CharDevice
+        } else {
+            ItemKind::File
+        }
     }
 }
 
@@ -40,8 +139,16 @@ pub struct Item {
     pub(crate) name: String,
     pub(crate) size: u64,
     pub(crate) perms: String,
+    pub(crate) mode: u32,
     pub(crate) modified_date: DateTime<Local>,
     pub(crate) is_dir: bool,
+    pub(crate) kind: ItemKind,
+    /// Set while the recursive size of a directory is still being computed.
+    pub(crate) size_pending: bool,
+    /// Where a symlink points, as stored in the link.
+    pub(crate) link_target: Option<PathBuf>,
+    /// The git status, when the entry is in a repository and not clean.
+    pub(crate) git_status: Option<GitStatus>,
 }
 
 impl Default for Item {
@@ -50,8 +157,13 @@ impl Default for Item {
             name: String::new(),
             size: 0,
             perms: String::new(),
+            mode: 0,
             modified_date: Local.ymd(1970, 1, 1).and_hms(0, 0, 0),
             is_dir: false,
+            kind: ItemKind::File,
+            size_pending: false,
+            link_target: None,
+            git_status: None,
         }
     }
 }
@@ -71,25 +183,75 @@ impl Item {
         self
     }
 
-    fn with_perms(mut self, perms: &str) -> Self {
-        self.perms = perms.to_string();
+    fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self.perms = unix_mode::to_string(mode);
+        self
+    }
+
+    fn with_modified_date(mut self, modified_date: DateTime<Local>) -> Self {
+        self.modified_date = modified_date;
         self
     }
 
-    fn is_dir(mut self, dir: bool) -> Self {
+    fn with_is_dir(mut self, dir: bool) -> Self {
         self.is_dir = dir;
         self
     }
+
+    fn with_kind(mut self, kind: ItemKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    fn with_link_target(mut self, target: Option<PathBuf>) -> Self {
+        self.link_target = target;
+        self
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.kind == ItemKind::File && self.mode & 0o111 != 0
+    }
 }
 
+/// What a shell command run with `ShellCapture` printed.
+#[derive(Clone, Debug)]
+pub struct CommandOutput {
+    pub command: String,
+    pub text: String,
+    pub scroll: u16,
+    /// Whether the text is a diff, to be coloured as one.
+    pub diff: bool,
+}
+
+/// An entry relative to its repository's root, with its status.
+type GitTarget = (PathBuf, Option<GitStatus>);
+
+/// Renames waiting to be confirmed in the preview popup.
+#[derive(Clone, Debug)]
+pub struct RenamePreview {
+    pub renames: Vec<(PathBuf, PathBuf)>,
+    pub table_state: TableState,
+}
+
+/// A bulk rename waiting for the names to be edited in `file`, which lives
+/// in a private directory removed along with this.
 #[derive(Debug)]
-struct State {
+struct BulkRename {
+    dir: String,
+    temp_dir: TempDir,
+    file: PathBuf,
+    names: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Tab {
     active_panel: PanelKind,
     main_view: WalkerView,
     action_view: WalkerView,
 }
 
-impl Default for State {
+impl Default for Tab {
     fn default() -> Self {
         Self {
             active_panel: PanelKind::Main,
@@ -99,6 +261,95 @@ impl Default for State {
     }
 }
 
+impl Tab {
+    fn with_dir(dir: &str) -> Self {
+        let mut tab = Self::default();
+        tab.main_view.set_current_dir(dir);
+        tab.action_view.set_current_dir(dir);
+        tab
+    }
+
+    pub fn display_dir(&self, physical: bool) -> &str {
+        self.main_view.display_dir(physical)
+    }
+
+    pub fn git_branch(&self) -> Option<&str> {
+        self.main_view.git_repo().map(|repo| repo.branch.as_str())
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    tabs: Vec<Tab>,
+    active_tab: usize,
+    dual_pane: bool,
+    /// Show directories with symlinks resolved rather than as navigated.
+    physical_paths: bool,
+    theme: Theme,
+    theme_names: Vec<String>,
+    theme_config: Config,
+    status: Option<String>,
+    pending_key: Option<char>,
+    bookmarks: Bookmarks,
+    bookmark_manager: Option<TableState>,
+    frecency: Frecency,
+    jump_candidates: Vec<String>,
+    jump_selected: usize,
+    pattern_renames: Vec<(String, String)>,
+    pattern_plan: Result<Vec<(PathBuf, PathBuf)>>,
+    dir_sizes: DirSizes,
+    git_statuses: GitStatuses,
+    watcher: Option<Watcher>,
+    foreground_command: Option<(String, String)>,
+    running_command: Option<Capture>,
+    command_output: Option<CommandOutput>,
+    file_diff: Option<FileDiff>,
+    dir_comparison: Option<DirComparison>,
+    bulk_rename: Option<BulkRename>,
+    rename_preview: Option<RenamePreview>,
+    chmod_dialog: Option<ChmodDialog>,
+    ticks: usize,
+    choosing_files: bool,
+    should_quit: bool,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            tabs: vec![Tab::default()],
+            active_tab: 0,
+            dual_pane: false,
+            physical_paths: false,
+            theme: Theme::default(),
+            theme_names: vec![],
+            theme_config: Config::default(),
+            status: None,
+            pending_key: None,
+            bookmarks: Bookmarks::default(),
+            bookmark_manager: None,
+            frecency: Frecency::default(),
+            jump_candidates: vec![],
+            jump_selected: 0,
+            pattern_renames: vec![],
+            pattern_plan: Ok(vec![]),
+            dir_sizes: DirSizes::default(),
+            git_statuses: GitStatuses::default(),
+            watcher: None,
+            foreground_command: None,
+            running_command: None,
+            command_output: None,
+            file_diff: None,
+            dir_comparison: None,
+            bulk_rename: None,
+            rename_preview: None,
+            chmod_dialog: None,
+            ticks: 0,
+            choosing_files: false,
+            should_quit: false,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct App {
     state: State,
@@ -111,161 +362,1821 @@ impl App {
         }
     }
 
-    pub fn set_current_dir(&mut self, dir: &str) {
-        self.get_active_view_mut().set_current_dir(dir);
+    pub fn theme(&self) -> &Theme {
+        &self.state.theme
     }
 
-    pub fn current_dir(&self) -> &String {
-        self.get_active_view().current_dir()
+    /// Sets the active theme and the config the others are resolved from
+    /// when switched to, so a broken theme only matters once picked.
+    pub fn set_themes(&mut self, theme: Theme, config: Config) {
+        self.state.theme = theme;
+        self.state.theme_names = Theme::available(&config);
+        self.state.theme_config = config;
     }
 
-    pub fn current_contents(&self) -> &[Item] {
-        self.get_active_view().current_contents()
+    /// Switches to the next theme that resolves, reporting those that do
+    /// not.
+    pub fn cycle_theme(&mut self) {
+        let names = &self.state.theme_names;
+        let current = names
+            .iter()
+            .position(|name| *name == self.state.theme.name)
+            .map_or(0, |i| i + 1);
+        let mut error = None;
+        for name in names.iter().cycle().skip(current).take(names.len()) {
+            match Theme::resolve(&self.state.theme_config, Some(name)) {
+                Ok(theme) => {
+                    self.state.theme = theme;
+                    break;
+                }
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = error {
+            self.set_status(format!("{:#}", e));
+        }
     }
 
-    pub fn set_directory_table_state(&mut self, state: TableState) {
-        self.get_active_view_mut().set_directory_table_state(state);
+    pub fn select_theme(&mut self, name: &str) {
+        match Theme::resolve(&self.state.theme_config, Some(name)) {
+            Ok(theme) => self.state.theme = theme,
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
     }
 
-    pub fn directory_table_state(&self) -> &TableState {
-        self.get_active_view().directory_table_state()
+    pub fn status(&self) -> Option<&str> {
+        self.state.status.as_deref()
     }
 
-    pub fn directory_table_state_mut(&mut self) -> &mut TableState {
-        self.get_active_view_mut().directory_table_state_mut()
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.state.status = Some(status.into());
     }
 
-    pub fn text_input(&self) -> &Input {
-        self.get_active_view().text_input()
+    pub fn clear_status(&mut self) {
+        self.state.status = None;
     }
 
-    pub fn text_input_mut(&mut self) -> &mut Input {
-        self.get_active_view_mut().text_input_mut()
+    /// Advances the animation of the size spinner.
+    pub fn tick(&mut self) {
+        self.state.ticks = self.state.ticks.wrapping_add(1);
     }
 
-    pub fn is_editing(&self) -> bool {
-        self.get_active_view().is_editing()
+    pub fn spinner(&self) -> char {
+        const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+        FRAMES[self.state.ticks % FRAMES.len()]
     }
 
-    pub fn input_mode(&self) -> InputMode {
-        // self.get_active_view().input_mode()
-        self.main_panel().input_mode()
+    pub fn quit(&mut self) {
+        self.state.should_quit = true;
     }
 
-    pub fn load_dir(&mut self) -> Result<()> {
-        self.get_active_view_mut().load_dir();
-        Ok(())
+    pub fn should_quit(&self) -> bool {
+        self.state.should_quit
     }
 
-    pub fn main_panel(&self) -> &WalkerView {
-        &self.state.main_view
+    /// In choose-files mode opening a file picks it and quits.
+    pub fn set_choosing_files(&mut self, choosing_files: bool) {
+        self.state.choosing_files = choosing_files;
     }
 
-    pub fn action_panel(&self) -> &WalkerView {
-        &self.state.action_view
+    /// Enters the selected directory, or picks the selected file when
+    /// choosing files.
+    pub fn open_selected(&mut self) {
+        match self.get_active_view().selected_item() {
+            Some(item) if item.is_dir => self.move_into_child_dir(),
+            Some(_) if self.state.choosing_files => self.quit(),
+            _ => {}
+        }
     }
 
-    pub fn main_panel_mut(&mut self) -> &mut WalkerView {
-        &mut self.state.main_view
+    /// Goes to the directory the selected symlink's target really lives in,
+    /// with every symlink on the way resolved, and selects the target.
+    pub fn follow_link(&mut self) {
+        let item = match self.get_active_view().selected_item() {
+            Some(item) if item.link_target.is_some() => item.clone(),
+            Some(_) => {
+                self.set_status("Not a symlink".to_string());
+                return;
+            }
+            None => return,
+        };
+
+        let target = match std::fs::canonicalize(&item.name) {
+            Ok(target) => target,
+            Err(e) => {
+                self.set_status(format!("Cannot follow {}: {}", item.name, e));
+                return;
+            }
+        };
+        if let Some(dir) = target.parent() {
+            self.set_current_dir(&dir.display().to_string());
+            self.select_path(&target.display().to_string());
+        }
     }
 
-    pub fn action_panel_mut(&mut self) -> &mut WalkerView {
-        &mut self.state.action_view
+    /// Selects the entry at `path` in the active panel, if it is listed.
+    pub fn select_path(&mut self, path: &str) {
+        self.get_active_view_mut().select_by_name(path);
     }
 
-    fn get_active_view(&self) -> &WalkerView {
-        match self.state.active_panel {
-            PanelKind::Main => &self.state.main_view,
-            PanelKind::Secondary => &self.state.action_view,
+    pub fn toggle_mark(&mut self) {
+        self.get_active_view_mut().toggle_mark();
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.get_active_view_mut().clear_marks();
+    }
+
+    /// Full paths of the active panel's marked entries, or of the selected
+    /// entry when nothing is marked.
+    pub fn chosen_paths(&self) -> Vec<String> {
+        self.get_active_view()
+            .marked_or_selected()
+            .iter()
+            .map(|item| item.name.clone())
+            .collect()
+    }
+
+    /// The prefix of a two key sequence such as `m<letter>`, if one was typed.
+    pub fn pending_key(&self) -> Option<char> {
+        self.state.pending_key
+    }
+
+    pub fn start_key_sequence(&mut self, prefix: char) {
+        self.state.pending_key = Some(prefix);
+        match prefix {
+            'Y' => self.set_status(
+                "Link into the other panel: s symlink, r relative symlink, h hard link".to_string(),
+            ),
+            'g' => self.set_status("Git: s stage, u unstage, x discard, d diff".to_string()),
+            _ => {}
         }
     }
 
-    fn get_active_view_mut(&mut self) -> &mut WalkerView {
-        match self.state.active_panel {
-            PanelKind::Main => &mut self.state.main_view,
-            PanelKind::Secondary => &mut self.state.action_view,
+    pub fn cancel_key_sequence(&mut self) {
+        self.state.pending_key = None;
+    }
+
+    pub fn complete_key_sequence(&mut self, key: char) {
+        match self.state.pending_key.take() {
+            Some('m') => self.set_bookmark(key),
+            Some('\'') => self.jump_to_bookmark(key),
+            Some('g') => match key {
+                's' => self.git_stage(),
+                'u' => self.git_unstage(),
+                'x' => self.start_git_discard(),
+                'd' => self.git_diff(),
+                _ => {}
+            },
+            Some('Y') => match key {
+                's' => self.link_chosen(LinkKind::Absolute),
+                'r' => self.link_chosen(LinkKind::Relative),
+                'h' => self.link_chosen(LinkKind::Hard),
+                _ => {}
+            },
+            _ => {}
         }
     }
 
-    pub fn move_selection_up(&mut self) {
-        self.get_active_view_mut().move_selection_up();
+    pub fn set_bookmarks(&mut self, bookmarks: Bookmarks) {
+        self.state.bookmarks = bookmarks;
     }
 
-    pub fn move_selection_down(&mut self) {
-        self.get_active_view_mut().move_selection_down();
+    pub fn bookmarks(&self) -> &Bookmarks {
+        &self.state.bookmarks
     }
 
-    pub fn move_into_child_dir(&mut self) {
-        self.get_active_view_mut().move_into_child_dir();
+    pub fn set_bookmark(&mut self, key: char) {
+        if !key.is_ascii_alphabetic() {
+            self.set_status(format!("Bookmarks need a letter, not '{}'", key));
+            return;
+        }
+
+        let dir = self.current_dir().clone();
+        match self.state.bookmarks.set(key, &dir) {
+            Ok(()) => self.set_status(format!("Bookmarked {} as '{}'", dir, key)),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
     }
 
-    pub fn move_upto_parent_dir(&mut self) {
-        self.get_active_view_mut().move_upto_parent_dir();
+    pub fn jump_to_bookmark(&mut self, key: char) {
+        let path = match self.state.bookmarks.get(key) {
+            Some(bookmark) => bookmark.path.clone(),
+            None => {
+                self.set_status(format!("No bookmark '{}'", key));
+                return;
+            }
+        };
+
+        if !Path::new(&path).is_dir() {
+            self.set_status(format!("Bookmarked directory {} no longer exists", path));
+            return;
+        }
+
+        self.set_current_dir(&path);
     }
 
-    pub fn start_rename_file(&mut self) {
-        self.get_active_view_mut().start_rename_file();
+    pub fn is_bookmark_manager_open(&self) -> bool {
+        self.state.bookmark_manager.is_some()
     }
 
-    pub fn set_input_mode(&mut self, input_mode: InputMode) {
-        self.get_active_view_mut().set_input_mode(input_mode);
-        match input_mode {
-            InputMode::Normal => {
-                self.state.active_panel = PanelKind::Main;
+    pub fn open_bookmark_manager(&mut self) {
+        let mut table_state = TableState::default();
+        table_state.select(Some(0));
+        self.state.bookmark_manager = Some(table_state);
+    }
+
+    pub fn close_bookmark_manager(&mut self) {
+        self.state.bookmark_manager = None;
+    }
+
+    pub fn bookmark_manager_state_mut(&mut self) -> Option<&mut TableState> {
+        self.state.bookmark_manager.as_mut()
+    }
+
+    fn selected_bookmark(&self) -> Option<usize> {
+        self.state
+            .bookmark_manager
+            .as_ref()
+            .and_then(|s| s.selected())
+            .filter(|idx| *idx < self.state.bookmarks.entries().len())
+    }
+
+    pub fn move_bookmark_selection(&mut self, down: bool) {
+        let count = self.state.bookmarks.entries().len();
+        if let Some(table_state) = self.state.bookmark_manager.as_mut() {
+            if count == 0 {
+                return;
             }
-            _ => {
-                self.state.active_panel = PanelKind::Secondary;
+            let selected = table_state.selected().unwrap_or(0);
+            let next = if down {
+                (selected + 1) % count
+            } else {
+                (selected + count - 1) % count
+            };
+            table_state.select(Some(next));
+        }
+    }
+
+    pub fn jump_to_selected_bookmark(&mut self) {
+        if let Some(idx) = self.selected_bookmark() {
+            let key = self.state.bookmarks.entries()[idx].key;
+            self.close_bookmark_manager();
+            self.jump_to_bookmark(key);
+        }
+    }
+
+    pub fn delete_selected_bookmark(&mut self) {
+        if let Some(idx) = self.selected_bookmark() {
+            if let Err(e) = self.state.bookmarks.remove(idx) {
+                self.set_status(format!("{:#}", e));
+            }
+            let count = self.state.bookmarks.entries().len();
+            if let Some(table_state) = self.state.bookmark_manager.as_mut() {
+                table_state.select(Some(idx.min(count.saturating_sub(1))));
             }
         }
     }
 
-    pub fn rename_file(&mut self) {
-        self.get_active_view_mut().rename_file();
+    pub fn start_rename_bookmark(&mut self) {
+        if let Some(idx) = self.selected_bookmark() {
+            let name = self.state.bookmarks.entries()[idx].name.clone();
+            self.get_active_view_mut()
+                .start_input(EditingKind::RenameBookmark, &name);
+        }
     }
 
-    pub fn initiate_file_copy(&mut self) {
-        self.get_active_view_mut().initiate_file_copy();
-        self.state.active_panel = PanelKind::Secondary;
-        self.get_active_view_mut().initiate_file_copy();
-        let selected_dir = self
-            .main_panel()
-            .selected_item()
-            .map_or(String::new(), |i| i.name.clone());
-        self.action_panel_mut().set_current_dir(&selected_dir);
+    pub fn rename_bookmark(&mut self) {
+        if let Some(idx) = self.selected_bookmark() {
+            let name = self.text_input().value().trim().to_string();
+            if !name.is_empty() {
+                if let Err(e) = self.state.bookmarks.rename(idx, &name) {
+                    self.set_status(format!("{:#}", e));
+                }
+            }
+        }
+        self.set_input_mode(InputMode::Normal);
+    }
+
+    pub fn set_frecency(&mut self, frecency: Frecency) {
+        self.state.frecency = frecency;
     }
 
-    pub fn delete_file(&mut self) -> Result<()> {
-        if self.state.active_panel == PanelKind::Main {
-            if let Some(f) = self.state.main_view.selected_item() {
-                let full_path = Path::new(&self.state.main_view.current_dir()).join(&f.name);
-                std::fs::remove_file(full_path)?;
-                self.load_dir()?;
+    /// Counts a visit to the current directory if it differs from `previous`.
+    fn record_visit(&mut self, previous: &str) {
+        let dir = self.current_dir().clone();
+        if dir != previous {
+            if let Err(e) = self.state.frecency.visit(&dir) {
+                self.set_status(format!("{:#}", e));
             }
         }
+    }
 
-        Ok(())
+    /// Writes back the visits not saved yet, e.g. on exit.
+    pub fn save_frecency(&mut self) -> Result<()> {
+        self.state.frecency.save()
     }
-}
 
-pub fn get_contents(path: &str) -> Result<Vec<Item>> {
-    // FIXME: Remove use of unwrap
-    let contents = WalkDir::new(path)
-        .sort_by_file_name()
-        .max_depth(1)
-        .into_iter()
-        .map(|ref f| {
-            let perms = format!(
-                "{:o}",
-                f.as_ref().unwrap().metadata().unwrap().permissions().mode()
-            );
-            let perms_octal: u32 = u32::from_str_radix(&perms, 8).unwrap();
+    pub fn start_jump(&mut self) {
+        self.get_active_view_mut()
+            .start_input(EditingKind::Jump, "");
+        self.update_jump_candidates();
+    }
 
-            Item::new()
-                .with_name(&(f.as_ref().unwrap().path().display().to_string()))
-                .with_size(f.as_ref().unwrap().metadata().unwrap().len())
-                .with_perms(&unix_mode::to_string(perms_octal))
-        })
-        .skip(1)
-        .collect();
-    Ok(contents)
+    /// Visited directories matching the jump prompt, best first.
+    pub fn jump_candidates(&self) -> &[String] {
+        &self.state.jump_candidates
+    }
+
+    /// Looks for the directories matching the jump prompt again, after it
+    /// changed, and selects the best.
+    pub fn update_jump_candidates(&mut self) {
+        let mut candidates = self
+            .state
+            .frecency
+            .matches(self.text_input().value(), self.current_dir());
+        candidates.truncate(50);
+        self.state.jump_candidates = candidates;
+        self.state.jump_selected = 0;
+    }
+
+    pub fn jump_selected(&self) -> usize {
+        self.state.jump_selected
+    }
+
+    pub fn move_jump_selection(&mut self, down: bool) {
+        let count = self.jump_candidates().len();
+        if count == 0 {
+            return;
+        }
+
+        let selected = self.state.jump_selected.min(count - 1);
+        self.state.jump_selected = if down {
+            (selected + 1) % count
+        } else {
+            (selected + count - 1) % count
+        };
+    }
+
+    pub fn complete_jump(&mut self) {
+        let candidates = std::mem::take(&mut self.state.jump_candidates);
+        self.set_input_mode(InputMode::Normal);
+        match candidates.get(self.state.jump_selected) {
+            Some(dir) => self.set_current_dir(dir),
+            None => self.set_status("No matching directory"),
+        }
+    }
+
+    pub fn start_command(&mut self) {
+        self.get_active_view_mut()
+            .start_input(EditingKind::Command, "");
+    }
+
+    /// Tab completion for the command line.
+    pub fn complete_command(&mut self) {
+        let line = self.text_input().value().to_string();
+        if let Some(completed) = command::complete(&line, self.current_dir()) {
+            *self.text_input_mut() = Input::default().with_value(completed);
+        }
+    }
+
+    pub fn run_command(&mut self) {
+        let line = self.text_input().value().to_string();
+        self.set_input_mode(InputMode::Normal);
+        let command = match Command::parse(&line, self.current_dir()) {
+            Ok(command) => command,
+            Err(e) => {
+                self.set_status(format!("{:#}", e));
+                return;
+            }
+        };
+
+        match command {
+            Command::Cd(dir) => {
+                if dir.is_dir() {
+                    self.set_current_dir(&dir.display().to_string());
+                } else {
+                    self.set_status(format!("{}: not a directory", dir.display()));
+                }
+            }
+            Command::Mkdir(path) => self.create(&path, true),
+            Command::Touch(path) => self.create(&path, false),
+            Command::Sort(sort) => self.update_list_options(|o| o.sort = sort),
+            Command::Filter(filter) => self.update_list_options(|o| o.filter = filter),
+            Command::Set(setting) => self.apply_setting(setting),
+            Command::Bookmark(key) => self.set_bookmark(key),
+            Command::Quit => self.quit(),
+        }
+    }
+
+    fn apply_setting(&mut self, setting: Setting) {
+        match setting {
+            Setting::Hidden(on) => self.update_list_options(|o| o.show_hidden = on),
+            Setting::Reverse(on) => self.update_list_options(|o| o.reverse = on),
+            Setting::DirsFirst(on) => self.update_list_options(|o| o.dirs_first = on),
+            Setting::Sort(sort) => self.update_list_options(|o| o.sort = sort),
+            Setting::DualPane(on) => self.set_dual_pane(on),
+            Setting::Physical(on) => self.set_physical_paths(on),
+            Setting::GitIgnored(on) => self.set_git_ignored(on),
+            Setting::Theme(name) => self.select_theme(&name),
+        }
+    }
+
+    pub fn start_new_file(&mut self) {
+        self.get_active_view_mut()
+            .start_input(EditingKind::NewFile, "");
+    }
+
+    pub fn start_new_directory(&mut self) {
+        self.get_active_view_mut()
+            .start_input(EditingKind::NewDirectory, "");
+    }
+
+    /// Creates what the new file or directory prompt names, relative to the
+    /// current directory.
+    pub fn create_from_input(&mut self) {
+        let dir = matches!(
+            self.input_mode(),
+            InputMode::Editing(EditingKind::NewDirectory)
+        );
+        let name = self.text_input().value().trim().to_string();
+        self.set_input_mode(InputMode::Normal);
+        if name.is_empty() {
+            return;
+        }
+
+        let path = command::resolve_path(self.current_dir(), &name);
+        self.create(&path, dir || name.ends_with('/'));
+    }
+
+    /// Creates a directory or an empty file at `path`, parents included, and
+    /// selects what appeared in the current directory.
+    pub fn create(&mut self, path: &Path, dir: bool) {
+        let result = if dir {
+            ops::create_dir(path)
+        } else {
+            ops::create_file(path)
+        };
+        if let Err(e) = result {
+            self.set_status(format!("{:#}", e));
+            return;
+        }
+
+        self.reload_panels();
+        let current_dir = Path::new(self.current_dir()).to_path_buf();
+        if let Ok(relative) = path.strip_prefix(&current_dir) {
+            if let Some(first) = relative.components().next() {
+                self.select_path(&current_dir.join(first).display().to_string());
+            }
+        }
+        self.set_status(format!("Created {}", path.display()));
+    }
+
+    /// Prompts for a shell command, run with the terminal handed over to it
+    /// or with its output `capture`d into a popup.
+    pub fn start_shell_command(&mut self, capture: bool) {
+        let kind = if capture {
+            EditingKind::ShellCapture
+        } else {
+            EditingKind::Shell
+        };
+        self.get_active_view_mut().start_input(kind, "");
+    }
+
+    fn placeholders(&self) -> Placeholders {
+        Placeholders {
+            selected: self
+                .get_active_view()
+                .selected_item()
+                .map(|item| item.name.clone()),
+            chosen: self.chosen_paths(),
+            dir: self.current_dir().clone(),
+            other_dir: self.view(self.other_panel()).current_dir().clone(),
+        }
+    }
+
+    /// Runs the command typed at the shell prompt. Foreground commands are
+    /// left for the UI loop, which owns the terminal.
+    pub fn run_shell_command(&mut self) {
+        let capture = matches!(
+            self.input_mode(),
+            InputMode::Editing(EditingKind::ShellCapture)
+        );
+        let command = shell::expand(self.text_input().value().trim(), &self.placeholders());
+        let dir = self.current_dir().clone();
+        self.set_input_mode(InputMode::Normal);
+        if command.is_empty() {
+            return;
+        }
+
+        if !capture {
+            self.state.foreground_command = Some((command, dir));
+            return;
+        }
+
+        match Capture::start(&command, &dir) {
+            Ok(capture) => self.state.running_command = Some(capture),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+    }
+
+    /// The captured command still running, if any.
+    pub fn running_command(&self) -> Option<&Capture> {
+        self.state.running_command.as_ref()
+    }
+
+    /// Shows the output of the captured command once it has exited.
+    pub fn update_running_command(&mut self) {
+        let text = match self.state.running_command.as_ref() {
+            Some(capture) => match capture.finished() {
+                Some(text) => text,
+                None => return,
+            },
+            None => return,
+        };
+        let command = match self.state.running_command.take() {
+            Some(capture) => capture.command,
+            None => return,
+        };
+        match text {
+            Ok(text) => {
+                self.state.command_output = Some(CommandOutput {
+                    command,
+                    text,
+                    scroll: 0,
+                    diff: false,
+                })
+            }
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+        self.rescan_panels();
+    }
+
+    pub fn kill_running_command(&mut self) {
+        if let Some(capture) = self.state.running_command.take() {
+            capture.kill();
+            self.set_status(format!("Killed {}", capture.command));
+            self.rescan_panels();
+        }
+    }
+
+    /// The command and directory of a foreground shell command waiting to
+    /// be run.
+    pub fn take_foreground_command(&mut self) -> Option<(String, String)> {
+        self.state.foreground_command.take()
+    }
+
+    pub fn finish_foreground_command(&mut self, result: Result<ExitStatus>) {
+        let succeeded = match result {
+            Ok(status) if status.success() => true,
+            Ok(status) => {
+                self.set_status(format!("Command failed: {}", status));
+                false
+            }
+            Err(e) => {
+                self.set_status(format!("{:#}", e));
+                false
+            }
+        };
+
+        match self.state.bulk_rename.take() {
+            Some(bulk) if succeeded => self.read_bulk_rename(bulk),
+            Some(_) => {}
+            None => self.rescan_panels(),
+        }
+    }
+
+    /// Opens `$VISUAL` or `$EDITOR` on the names of the marked entries, or of
+    /// every entry when nothing is marked, to rename them all at once.
+    pub fn start_bulk_rename(&mut self) {
+        let view = self.get_active_view();
+        let marked = view.marked_items();
+        let items = if marked.is_empty() {
+            view.current_contents().iter().collect()
+        } else {
+            marked
+        };
+        let names: Vec<String> = items
+            .into_iter()
+            .map(|item| listing::file_name(item).to_string())
+            .collect();
+        if names.is_empty() {
+            return;
+        }
+        if names.iter().any(|name| name.contains('\n')) {
+            self.set_status("Cannot bulk rename names containing newlines");
+            return;
+        }
+
+        let (temp_dir, file) = match write_names(&names) {
+            Ok(written) => written,
+            Err(e) => {
+                self.set_status(format!("{:#}", e));
+                return;
+            }
+        };
+
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string());
+        let dir = self.current_dir().clone();
+        self.state.foreground_command = Some((
+            format!("{} {}", editor, shell::quote(&file.display().to_string())),
+            dir.clone(),
+        ));
+        self.state.bulk_rename = Some(BulkRename {
+            dir,
+            temp_dir,
+            file,
+            names,
+        });
+    }
+
+    fn read_bulk_rename(&mut self, bulk: BulkRename) {
+        let contents = std::fs::read_to_string(&bulk.file);
+        let (dir, file, names) = (bulk.dir, bulk.file, bulk.names);
+        drop(bulk.temp_dir);
+        let contents = match contents {
+            Ok(contents) => contents,
+            Err(e) => {
+                self.set_status(format!("failed to read {}: {}", file.display(), e));
+                return;
+            }
+        };
+
+        let mut new_names: Vec<String> = contents.lines().map(str::to_string).collect();
+        while new_names.last().is_some_and(|name| name.is_empty()) {
+            new_names.pop();
+        }
+        match rename::plan(Path::new(&dir), &names, &new_names) {
+            Ok(renames) => self.preview_renames(renames),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+    }
+
+    /// Whether marked directories are the target, in which case chmod and
+    /// chown apply to everything inside them too.
+    fn targets_marked_dirs(&self) -> bool {
+        self.get_active_view()
+            .marked_items()
+            .iter()
+            .any(|item| item.kind == ItemKind::Directory)
+    }
+
+    /// Opens the chmod dialog on the marked (or selected) entries, starting
+    /// from the mode of the first.
+    pub fn open_chmod(&mut self) {
+        let mode = match self.get_active_view().marked_or_selected().first() {
+            Some(item) => item.mode,
+            None => return,
+        };
+        let recursive = self.targets_marked_dirs();
+        self.state.chmod_dialog = Some(ChmodDialog::new(self.chosen_paths(), mode, recursive));
+    }
+
+    pub fn chmod_dialog(&self) -> Option<&ChmodDialog> {
+        self.state.chmod_dialog.as_ref()
+    }
+
+    pub fn chmod_dialog_mut(&mut self) -> Option<&mut ChmodDialog> {
+        self.state.chmod_dialog.as_mut()
+    }
+
+    pub fn close_chmod(&mut self) {
+        self.state.chmod_dialog = None;
+    }
+
+    pub fn apply_chmod(&mut self) {
+        let dialog = match self.state.chmod_dialog.take() {
+            Some(dialog) => dialog,
+            None => return,
+        };
+
+        let mut changed = 0;
+        for target in &dialog.targets {
+            let path = Path::new(target);
+            match perms::chmod(path, dialog.mode, dialog.recursive && path.is_dir()) {
+                Ok(count) => changed += count,
+                Err(e) => {
+                    self.set_status(format!("{:#}", e));
+                    self.reload_panels();
+                    return;
+                }
+            }
+        }
+        self.set_status(format!(
+            "Set the mode of {} entries to {:04o}",
+            changed, dialog.mode
+        ));
+        self.reload_panels();
+    }
+
+    /// Prompts for the new owner of the marked (or selected) entries, filled
+    /// in with the current one.
+    pub fn start_chown(&mut self) {
+        let owner = match self.chosen_paths().first() {
+            Some(path) => perms::owner_of(Path::new(path)).unwrap_or_default(),
+            None => return,
+        };
+        self.get_active_view_mut()
+            .start_input(EditingKind::Chown, &owner);
+    }
+
+    pub fn complete_owner(&mut self) {
+        if let Some(completed) = perms::complete_owner(self.text_input().value()) {
+            *self.text_input_mut() = Input::default().with_value(completed);
+        }
+    }
+
+    pub fn apply_chown(&mut self) {
+        let spec = self.text_input().value().trim().to_string();
+        let targets = self.chosen_paths();
+        let recursive = self.targets_marked_dirs();
+        self.set_input_mode(InputMode::Normal);
+
+        let (uid, gid) = match perms::parse_owner(&spec) {
+            Ok(ids) => ids,
+            Err(e) => {
+                self.set_status(format!("{:#}", e));
+                return;
+            }
+        };
+
+        let mut changed = 0;
+        for target in &targets {
+            let path = Path::new(target);
+            match perms::chown(path, uid, gid, recursive && path.is_dir()) {
+                Ok(count) => changed += count,
+                Err(e) => {
+                    self.set_status(format!("{:#}", e));
+                    self.reload_panels();
+                    return;
+                }
+            }
+        }
+        self.set_status(format!(
+            "Changed the owner of {} entries to {}",
+            changed, spec
+        ));
+        self.reload_panels();
+    }
+
+    /// Prompts for a pattern to rename the marked (or selected) entries with.
+    pub fn start_pattern_rename(&mut self) {
+        if self.get_active_view().marked_or_selected().is_empty() {
+            return;
+        }
+        self.get_active_view_mut()
+            .start_input(EditingKind::PatternRename, "{name}");
+        self.update_pattern_preview();
+    }
+
+    /// The names of the marked (or selected) entries next to what the
+    /// pattern typed so far turns them into.
+    pub fn pattern_renames(&self) -> &[(String, String)] {
+        &self.state.pattern_renames
+    }
+
+    /// The renames the pattern makes, checked like any other rename.
+    pub fn pattern_rename_plan(&self) -> &Result<Vec<(PathBuf, PathBuf)>> {
+        &self.state.pattern_plan
+    }
+
+    /// Works out the renames again after the pattern changed.
+    pub fn update_pattern_preview(&mut self) {
+        let renames = RenamePattern::parse(self.text_input().value()).and_then(|pattern| {
+            self.get_active_view()
+                .marked_or_selected()
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    let new_name = pattern.apply(item, i + 1)?;
+                    Ok((listing::file_name(item).to_string(), new_name))
+                })
+                .collect::<Result<Vec<_>>>()
+        });
+
+        let (renames, plan) = match renames {
+            Ok(renames) => {
+                let (old_names, new_names): (Vec<String>, Vec<String>) =
+                    renames.iter().cloned().unzip();
+                let plan = rename::plan(Path::new(self.current_dir()), &old_names, &new_names);
+                (renames, plan)
+            }
+            Err(e) => (vec![], Err(e)),
+        };
+        self.state.pattern_renames = renames;
+        self.state.pattern_plan = plan;
+    }
+
+    /// Applies the pattern, or reports why it cannot be applied.
+    pub fn apply_pattern_rename(&mut self) {
+        self.set_input_mode(InputMode::Normal);
+        let plan = std::mem::replace(&mut self.state.pattern_plan, Ok(vec![]));
+        self.state.pattern_renames.clear();
+        match plan {
+            Ok(renames) if renames.is_empty() => self.set_status("Nothing to rename"),
+            Ok(renames) => self.apply_renames(&renames),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+    }
+
+    fn apply_renames(&mut self, renames: &[(PathBuf, PathBuf)]) {
+        match rename::apply(renames) {
+            Ok(()) => self.set_status(format!("Renamed {} entries", renames.len())),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+        for (from, _) in renames {
+            self.state.dir_sizes.invalidate(&from.display().to_string());
+        }
+        self.reload_panels();
+    }
+
+    /// Shows `renames` for confirmation before applying them.
+    pub fn preview_renames(&mut self, renames: Vec<(PathBuf, PathBuf)>) {
+        if renames.is_empty() {
+            self.set_status("Nothing to rename");
+            return;
+        }
+
+        let mut table_state = TableState::default();
+        table_state.select(Some(0));
+        self.state.rename_preview = Some(RenamePreview {
+            renames,
+            table_state,
+        });
+    }
+
+    pub fn rename_preview(&self) -> Option<&RenamePreview> {
+        self.state.rename_preview.as_ref()
+    }
+
+    pub fn rename_preview_mut(&mut self) -> Option<&mut RenamePreview> {
+        self.state.rename_preview.as_mut()
+    }
+
+    pub fn move_rename_preview_selection(&mut self, down: bool) {
+        if let Some(preview) = self.state.rename_preview.as_mut() {
+            let count = preview.renames.len();
+            let selected = preview.table_state.selected().unwrap_or(0);
+            let next = if down {
+                (selected + 1).min(count - 1)
+            } else {
+                selected.saturating_sub(1)
+            };
+            preview.table_state.select(Some(next));
+        }
+    }
+
+    pub fn cancel_renames(&mut self) {
+        self.state.rename_preview = None;
+    }
+
+    pub fn confirm_renames(&mut self) {
+        let preview = match self.state.rename_preview.take() {
+            Some(preview) => preview,
+            None => return,
+        };
+
+        self.apply_renames(&preview.renames);
+    }
+
+    /// Re-reads both panels and recomputes their directory sizes, e.g. after
+    /// a shell command that may have changed anything.
+    fn rescan_panels(&mut self) {
+        for panel in [PanelKind::Main, PanelKind::Secondary] {
+            let dir = self.view(panel).current_dir().clone();
+            if !dir.is_empty() {
+                self.state.dir_sizes.invalidate(&dir);
+                self.state.git_statuses.invalidate(&dir);
+            }
+        }
+        self.reload_panels();
+    }
+
+    pub fn command_output(&self) -> Option<&CommandOutput> {
+        self.state.command_output.as_ref()
+    }
+
+    pub fn close_command_output(&mut self) {
+        self.state.command_output = None;
+    }
+
+    pub fn scroll_command_output(&mut self, down: bool) {
+        if let Some(output) = self.state.command_output.as_mut() {
+            output.scroll = if down {
+                output.scroll.saturating_add(1)
+            } else {
+                output.scroll.saturating_sub(1)
+            };
+        }
+    }
+
+    /// Diffs the files selected in the two panels, or compares the panels'
+    /// directories when they are not both on a file.
+    pub fn compare_panels(&mut self) {
+        if !self.state.dual_pane {
+            self.set_status(
+                "Comparing needs both panels; turn on dual-pane mode first".to_string(),
+            );
+            return;
+        }
+
+        let (left, right) = (self.main_panel(), self.action_panel());
+        let selected_file = |view: &WalkerView| {
+            view.selected_item()
+                .map(|item| PathBuf::from(&item.name))
+                .filter(|path| path.is_file())
+        };
+        if let (Some(left), Some(right)) = (selected_file(left), selected_file(right)) {
+            match FileDiff::new(&left, &right) {
+                Ok(Some(diff)) => self.state.file_diff = Some(diff),
+                Ok(None) => self.set_status("The files are identical".to_string()),
+                Err(e) => self.set_status(format!("{:#}", e)),
+            }
+            return;
+        }
+
+        let (left, right) = (
+            PathBuf::from(left.current_dir()),
+            PathBuf::from(right.current_dir()),
+        );
+        match DirComparison::new(&left, &right, false) {
+            Ok(comparison) if comparison.entries.is_empty() => self.set_status(format!(
+                "The directories are identical ({} entries)",
+                comparison.identical
+            )),
+            Ok(comparison) => self.state.dir_comparison = Some(comparison),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+    }
+
+    pub fn file_diff(&self) -> Option<&FileDiff> {
+        self.state.file_diff.as_ref()
+    }
+
+    pub fn close_file_diff(&mut self) {
+        self.state.file_diff = None;
+    }
+
+    pub fn scroll_file_diff(&mut self, down: bool) {
+        if let Some(diff) = self.state.file_diff.as_mut() {
+            let last = diff.line_count().saturating_sub(1) as u16;
+            diff.scroll = if down {
+                diff.scroll.saturating_add(1).min(last)
+            } else {
+                diff.scroll.saturating_sub(1)
+            };
+        }
+    }
+
+    /// Switches between the unified and side-by-side layouts.
+    pub fn toggle_diff_layout(&mut self) {
+        if let Some(diff) = self.state.file_diff.as_mut() {
+            diff.side_by_side = !diff.side_by_side;
+            diff.scroll = 0;
+        }
+    }
+
+    pub fn dir_comparison(&self) -> Option<&DirComparison> {
+        self.state.dir_comparison.as_ref()
+    }
+
+    pub fn dir_comparison_mut(&mut self) -> Option<&mut DirComparison> {
+        self.state.dir_comparison.as_mut()
+    }
+
+    pub fn close_dir_comparison(&mut self) {
+        self.state.dir_comparison = None;
+    }
+
+    /// Switches between comparing by size and modification time and
+    /// comparing contents.
+    pub fn toggle_comparison_by_content(&mut self) {
+        if let Some(comparison) = self.state.dir_comparison.as_mut() {
+            comparison.by_content = !comparison.by_content;
+            if let Err(e) = comparison.refresh() {
+                self.set_status(format!("{:#}", e));
+            }
+        }
+    }
+
+    /// Asks before copying the differences across, or says there are none
+    /// to copy that way.
+    pub fn start_sync(&mut self, direction: SyncDirection) {
+        let comparison = match self.state.dir_comparison.as_mut() {
+            Some(comparison) => comparison,
+            None => return,
+        };
+        if comparison.sync_question(direction).is_some() {
+            comparison.pending_sync = Some(direction);
+        } else {
+            self.set_status("Nothing to copy that way".to_string());
+        }
+    }
+
+    pub fn answer_sync(&mut self, yes: bool) {
+        let direction = self
+            .state
+            .dir_comparison
+            .as_mut()
+            .and_then(|comparison| comparison.pending_sync.take());
+        if let Some(direction) = direction.filter(|_| yes) {
+            self.sync_dirs(direction);
+        }
+    }
+
+    /// Copies the differences across and compares again.
+    fn sync_dirs(&mut self, direction: SyncDirection) {
+        let comparison = match self.state.dir_comparison.as_mut() {
+            Some(comparison) => comparison,
+            None => return,
+        };
+
+        let result = comparison.sync(direction);
+        let refreshed = comparison.refresh();
+        let dirs = [&comparison.left, &comparison.right].map(|dir| dir.display().to_string());
+        let remaining = comparison.entries.len();
+        match result.and_then(|copied| refreshed.map(|_| copied)) {
+            Ok(copied) => self.set_status(format!(
+                "Copied {} entries; {} differences left",
+                copied, remaining
+            )),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+        for dir in dirs {
+            self.state.dir_sizes.invalidate(&dir);
+        }
+        self.reload_panels();
+    }
+
+    pub fn is_dual_pane(&self) -> bool {
+        self.state.dual_pane
+    }
+
+    /// Shows both panels permanently, opening the second one on the
+    /// directory of the first when it has not been used yet.
+    pub fn set_dual_pane(&mut self, dual_pane: bool) {
+        self.state.dual_pane = dual_pane;
+        for tab in self.state.tabs.iter_mut() {
+            if dual_pane && tab.action_view.current_dir().is_empty() {
+                let dir = tab.main_view.current_dir().clone();
+                tab.action_view.set_current_dir(&dir);
+            }
+            if !dual_pane {
+                tab.active_panel = PanelKind::Main;
+            }
+        }
+    }
+
+    pub fn is_physical_paths(&self) -> bool {
+        self.state.physical_paths
+    }
+
+    pub fn set_physical_paths(&mut self, physical: bool) {
+        self.state.physical_paths = physical;
+    }
+
+    /// Whether ignored files get a status, which makes `git status` look
+    /// through directories such as `target`.
+    pub fn set_git_ignored(&mut self, ignored: bool) {
+        self.state.git_statuses.set_ignored(ignored);
+    }
+
+    pub fn toggle_dual_pane(&mut self) {
+        self.set_input_mode(InputMode::Normal);
+        self.set_dual_pane(!self.state.dual_pane);
+    }
+
+    /// Whether the second panel is on screen, either permanently or while
+    /// choosing a copy/move destination.
+    pub fn shows_action_panel(&self) -> bool {
+        self.state.dual_pane || self.input_mode().is_transfer()
+    }
+
+    /// Whether a copy/move destination is being picked by navigating the
+    /// second panel rather than typed in.
+    pub fn is_browsing_destination(&self) -> bool {
+        !self.state.dual_pane && self.input_mode().is_transfer()
+    }
+
+    pub fn active_panel(&self) -> PanelKind {
+        self.tab().active_panel
+    }
+
+    pub fn switch_panel(&mut self) {
+        if !self.state.dual_pane {
+            return;
+        }
+
+        let tab = self.tab_mut();
+        tab.active_panel = match tab.active_panel {
+            PanelKind::Main => PanelKind::Secondary,
+            PanelKind::Secondary => PanelKind::Main,
+        };
+    }
+
+    pub fn tabs(&self) -> &[Tab] {
+        &self.state.tabs
+    }
+
+    pub fn active_tab(&self) -> usize {
+        self.state.active_tab
+    }
+
+    /// Opens a new tab on the current directory and switches to it.
+    pub fn open_tab(&mut self) {
+        let tab = Tab::with_dir(self.main_panel().current_dir());
+        self.state.active_tab += 1;
+        self.state.tabs.insert(self.state.active_tab, tab);
+    }
+
+    /// Closes the active tab; the last remaining tab is never closed.
+    pub fn close_tab(&mut self) {
+        if self.state.tabs.len() <= 1 {
+            return;
+        }
+
+        self.state.tabs.remove(self.state.active_tab);
+        if self.state.active_tab >= self.state.tabs.len() {
+            self.state.active_tab = self.state.tabs.len() - 1;
+        }
+    }
+
+    pub fn next_tab(&mut self) {
+        self.state.active_tab = (self.state.active_tab + 1) % self.state.tabs.len();
+    }
+
+    pub fn previous_tab(&mut self) {
+        let count = self.state.tabs.len();
+        self.state.active_tab = (self.state.active_tab + count - 1) % count;
+    }
+
+    pub fn select_tab(&mut self, idx: usize) {
+        if idx < self.state.tabs.len() {
+            self.state.active_tab = idx;
+        }
+    }
+
+    fn tab(&self) -> &Tab {
+        &self.state.tabs[self.state.active_tab]
+    }
+
+    fn tab_mut(&mut self) -> &mut Tab {
+        &mut self.state.tabs[self.state.active_tab]
+    }
+
+    pub fn set_current_dir(&mut self, dir: &str) {
+        let previous = self.current_dir().clone();
+        self.get_active_view_mut().change_dir(dir);
+        self.record_visit(&previous);
+    }
+
+    pub fn current_dir(&self) -> &String {
+        self.get_active_view().current_dir()
+    }
+
+    pub fn text_input(&self) -> &Input {
+        self.get_active_view().text_input()
+    }
+
+    pub fn text_input_mut(&mut self) -> &mut Input {
+        self.get_active_view_mut().text_input_mut()
+    }
+
+    /// The mode of whichever panel is editing, so that a copy started in
+    /// the main panel stays active while the second panel is navigated.
+    pub fn input_mode(&self) -> InputMode {
+        self.editing_panel()
+            .map_or(InputMode::Normal, |panel| self.view(panel).input_mode())
+    }
+
+    fn editing_panel(&self) -> Option<PanelKind> {
+        [PanelKind::Main, PanelKind::Secondary]
+            .into_iter()
+            .find(|panel| matches!(self.view(*panel).input_mode(), InputMode::Editing(_)))
+    }
+
+    pub fn main_panel(&self) -> &WalkerView {
+        &self.tab().main_view
+    }
+
+    pub fn action_panel(&self) -> &WalkerView {
+        &self.tab().action_view
+    }
+
+    pub fn main_panel_mut(&mut self) -> &mut WalkerView {
+        &mut self.tab_mut().main_view
+    }
+
+    pub fn action_panel_mut(&mut self) -> &mut WalkerView {
+        &mut self.tab_mut().action_view
+    }
+
+    fn view(&self, panel: PanelKind) -> &WalkerView {
+        let tab = self.tab();
+        match panel {
+            PanelKind::Main => &tab.main_view,
+            PanelKind::Secondary => &tab.action_view,
+        }
+    }
+
+    fn view_mut(&mut self, panel: PanelKind) -> &mut WalkerView {
+        let tab = self.tab_mut();
+        match panel {
+            PanelKind::Main => &mut tab.main_view,
+            PanelKind::Secondary => &mut tab.action_view,
+        }
+    }
+
+    fn other_panel(&self) -> PanelKind {
+        match self.tab().active_panel {
+            PanelKind::Main => PanelKind::Secondary,
+            PanelKind::Secondary => PanelKind::Main,
+        }
+    }
+
+    fn get_active_view(&self) -> &WalkerView {
+        self.view(self.tab().active_panel)
+    }
+
+    fn get_active_view_mut(&mut self) -> &mut WalkerView {
+        self.view_mut(self.tab().active_panel)
+    }
+
+    pub fn move_selection_up(&mut self) {
+        self.get_active_view_mut().move_selection_up();
+    }
+
+    pub fn move_selection_down(&mut self) {
+        self.get_active_view_mut().move_selection_down();
+    }
+
+    pub fn move_into_child_dir(&mut self) {
+        let previous = self.current_dir().clone();
+        self.get_active_view_mut().move_into_child_dir();
+        self.record_visit(&previous);
+    }
+
+    pub fn move_upto_parent_dir(&mut self) {
+        let previous = self.current_dir().clone();
+        self.get_active_view_mut().move_upto_parent_dir();
+        self.record_visit(&previous);
+    }
+
+    pub fn list_options(&self) -> &ListOptions {
+        self.get_active_view().list_options()
+    }
+
+    /// Applies `change` to the active panel's list options and reports the
+    /// new ordering in the status line.
+    pub fn update_list_options(&mut self, change: impl FnOnce(&mut ListOptions)) {
+        let mut options = self.list_options().clone();
+        change(&mut options);
+        let description = options.describe();
+        match self.get_active_view_mut().set_list_options(options) {
+            Ok(()) => self.set_status(format!("Listing {}", description)),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.update_list_options(|o| o.sort = o.sort.next());
+    }
+
+    pub fn toggle_sort_reverse(&mut self) {
+        self.update_list_options(|o| o.reverse = !o.reverse);
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.update_list_options(|o| o.show_hidden = !o.show_hidden);
+    }
+
+    pub fn start_filter(&mut self) {
+        let filter = self.list_options().filter.clone().unwrap_or_default();
+        self.get_active_view_mut()
+            .start_input(EditingKind::Filter, &filter);
+    }
+
+    pub fn apply_filter(&mut self) {
+        let filter = self.text_input().value().trim().to_string();
+        self.set_input_mode(InputMode::Normal);
+        self.update_list_options(|o| o.filter = Some(filter).filter(|f| !f.is_empty()));
+    }
+
+    pub fn go_back(&mut self) {
+        let previous = self.current_dir().clone();
+        self.get_active_view_mut().go_back();
+        self.record_visit(&previous);
+    }
+
+    pub fn go_forward(&mut self) {
+        let previous = self.current_dir().clone();
+        self.get_active_view_mut().go_forward();
+        self.record_visit(&previous);
+    }
+
+    pub fn start_rename_file(&mut self) {
+        self.get_active_view_mut().start_rename_file();
+    }
+
+    pub fn set_input_mode(&mut self, input_mode: InputMode) {
+        match input_mode {
+            InputMode::Normal => {
+                self.main_panel_mut().set_input_mode(input_mode);
+                self.action_panel_mut().set_input_mode(input_mode);
+                if !self.state.dual_pane {
+                    self.tab_mut().active_panel = PanelKind::Main;
+                }
+            }
+            _ => {
+                self.get_active_view_mut().set_input_mode(input_mode);
+            }
+        }
+    }
+
+    pub fn rename_file(&mut self) {
+        let name = self.get_active_view().file_to_edit().name.clone();
+        match self.get_active_view_mut().rename_file() {
+            Ok(path) if path.display().to_string() != name => {
+                self.state.dir_sizes.invalidate(&name);
+                self.set_status(format!("Renamed to {}", path.display()));
+            }
+            Ok(_) => {}
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+    }
+
+    pub fn initiate_file_copy(&mut self) {
+        self.initiate_transfer(EditingKind::Copy);
+    }
+
+    pub fn initiate_file_move(&mut self) {
+        self.initiate_transfer(EditingKind::Move);
+    }
+
+    /// In dual-pane mode the destination defaults to the other panel's
+    /// directory; otherwise the second panel opens for picking one.
+    fn initiate_transfer(&mut self, kind: EditingKind) {
+        if self.state.dual_pane {
+            let destination = self.view(self.other_panel()).current_dir().clone();
+            self.get_active_view_mut()
+                .initiate_transfer(kind, &destination);
+            return;
+        }
+
+        self.main_panel_mut().initiate_transfer(kind, "");
+        if !self.main_panel().input_mode().is_transfer() {
+            return;
+        }
+
+        self.tab_mut().active_panel = PanelKind::Secondary;
+        let selected_dir = self
+            .main_panel()
+            .selected_item()
+            .map_or(String::new(), |i| i.name.clone());
+        self.action_panel_mut().set_current_dir(&selected_dir);
+    }
+
+    /// Copies or moves the item picked by `initiate_file_copy` or
+    /// `initiate_file_move` and reports the outcome in the status line.
+    pub fn complete_transfer(&mut self) {
+        let panel = match self.editing_panel() {
+            Some(panel) => panel,
+            None => return,
+        };
+        let view = self.view(panel);
+        let kind = match view.input_mode() {
+            InputMode::Editing(kind @ EditingKind::Copy)
+            | InputMode::Editing(kind @ EditingKind::Move) => kind,
+            _ => return,
+        };
+
+        let source = Path::new(&view.file_to_edit().name).to_path_buf();
+        let destination = if self.state.dual_pane {
+            Path::new(view.current_dir()).join(view.text_input().value())
+        } else {
+            Path::new(self.action_panel().current_dir()).to_path_buf()
+        };
+
+        let result = match kind {
+            EditingKind::Move => ops::move_into(&source, &destination),
+            _ => ops::copy_into(&source, &destination),
+        };
+        match result {
+            Ok(dest) => {
+                self.state.dir_sizes.invalidate(&dest.display().to_string());
+                if kind == EditingKind::Move {
+                    self.state
+                        .dir_sizes
+                        .invalidate(&source.display().to_string());
+                }
+                let verb = if kind == EditingKind::Move {
+                    "Moved"
+                } else {
+                    "Copied"
+                };
+                self.set_status(format!("{} to {}", verb, dest.display()));
+            }
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+
+        self.set_input_mode(InputMode::Normal);
+        self.reload_panels();
+    }
+
+    pub fn set_watcher(&mut self, watcher: Watcher) {
+        self.state.watcher = Some(watcher);
+    }
+
+    /// Keeps the directories of every panel watched and reloads the panels
+    /// showing one that changed on disk.
+    pub fn reload_changed_dirs(&mut self) {
+        let watcher = match self.state.watcher.as_mut() {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        let views: Vec<&WalkerView> = self
+            .state
+            .tabs
+            .iter()
+            .flat_map(|tab| [&tab.main_view, &tab.action_view])
+            .collect();
+        let git_dirs: HashSet<String> = views
+            .iter()
+            .filter_map(|view| view.git_repo())
+            .map(|repo| repo.git_dir())
+            .filter(|dir| dir.is_dir())
+            .map(|dir| dir.display().to_string())
+            .collect();
+        let mut dirs: HashSet<String> = views
+            .iter()
+            .map(|view| view.current_dir().clone())
+            .collect();
+        dirs.extend(git_dirs.iter().cloned());
+        watcher.watch(dirs);
+
+        for dir in watcher.changed_dirs() {
+            self.state.git_statuses.invalidate(&dir);
+            if git_dirs.contains(&dir) {
+                continue;
+            }
+            self.state.dir_sizes.changed(&dir);
+            for tab in self.state.tabs.iter_mut() {
+                for view in [&mut tab.main_view, &mut tab.action_view] {
+                    if *view.current_dir() == dir {
+                        let _ = view.load_dir();
+                        view.clamp_selection();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Re-reads the panels and recomputes their directory sizes.
+    pub fn refresh(&mut self) {
+        self.rescan_panels();
+        self.set_status("Reloaded");
+    }
+
+    /// Reloads both panels, e.g. after an operation that may touch either.
+    pub fn reload_panels(&mut self) {
+        for panel in [PanelKind::Main, PanelKind::Secondary] {
+            let view = self.view_mut(panel);
+            if !view.current_dir().is_empty() {
+                let _ = view.load_dir();
+                view.clamp_selection();
+            }
+        }
+    }
+
+    /// The repository root and the marked (or selected) entries relative to
+    /// it, with their status.
+    fn git_targets(&mut self) -> Option<(PathBuf, Vec<GitTarget>)> {
+        let view = self.get_active_view();
+        let root = match view.git_repo() {
+            Some(repo) => repo.root.clone(),
+            None => {
+                self.set_status("Not in a git repository".to_string());
+                return None;
+            }
+        };
+        let targets = view
+            .marked_or_selected()
+            .iter()
+            .filter_map(|item| {
+                let path = Path::new(&item.name).strip_prefix(&root).ok()?;
+                Some((path.to_path_buf(), item.git_status))
+            })
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return None;
+        }
+        Some((root, targets))
+    }
+
+    /// Reports how a git action went and reloads the statuses it changed.
+    fn finish_git_action(&mut self, root: &Path, result: Result<()>, done: String) {
+        match result {
+            Ok(()) => self.set_status(done),
+            Err(e) => self.set_status(format!("{:#}", e)),
+        }
+        self.state
+            .git_statuses
+            .invalidate(&root.display().to_string());
+        self.reload_panels();
+    }
+
+    pub fn git_stage(&mut self) {
+        if let Some((root, targets)) = self.git_targets() {
+            let paths: Vec<PathBuf> = targets.into_iter().map(|(path, _)| path).collect();
+            let result = git::stage(&root, &paths);
+            self.finish_git_action(&root, result, format!("Staged {} entries", paths.len()));
+        }
+    }
+
+    pub fn git_unstage(&mut self) {
+        if let Some((root, targets)) = self.git_targets() {
+            let paths: Vec<PathBuf> = targets
+                .into_iter()
+                .filter(|(_, status)| {
+                    matches!(status, Some(GitStatus::Staged) | Some(GitStatus::Modified))
+                })
+                .map(|(path, _)| path)
+                .collect();
+            if paths.is_empty() {
+                self.set_status("Nothing to unstage".to_string());
+                return;
+            }
+            let result = git::unstage(&root, &paths);
+            self.finish_git_action(&root, result, format!("Unstaged {} entries", paths.len()));
+        }
+    }
+
+    /// Asks before discarding, as the changes cannot be got back.
+    pub fn start_git_discard(&mut self) {
+        if self.git_targets().is_some() {
+            self.get_active_view_mut()
+                .start_input(EditingKind::ConfirmDiscard, "");
+        }
+    }
+
+    pub fn confirm_git_discard(&mut self) {
+        let answer = self.text_input().value().trim().to_lowercase();
+        self.set_input_mode(InputMode::Normal);
+        if answer != "y" && answer != "yes" {
+            return;
+        }
+
+        if let Some((root, targets)) = self.git_targets() {
+            let paths: Vec<PathBuf> = targets
+                .into_iter()
+                .filter(|(_, status)| *status == Some(GitStatus::Modified))
+                .map(|(path, _)| path)
+                .collect();
+            if paths.is_empty() {
+                self.set_status("No unstaged changes to discard".to_string());
+                return;
+            }
+            let result = git::discard(&root, &paths);
+            self.finish_git_action(
+                &root,
+                result,
+                format!("Discarded the changes to {} entries", paths.len()),
+            );
+        }
+    }
+
+    /// Shows the changes to the marked (or selected) entries in a popup.
+    pub fn git_diff(&mut self) {
+        if let Some((root, targets)) = self.git_targets() {
+            let (untracked, tracked): (Vec<_>, Vec<_>) = targets
+                .into_iter()
+                .partition(|(_, status)| *status == Some(GitStatus::Untracked));
+            let tracked: Vec<PathBuf> = tracked.into_iter().map(|(path, _)| path).collect();
+            let untracked: Vec<PathBuf> = untracked
+                .into_iter()
+                .map(|(path, _)| path)
+                .filter(|path| root.join(path).is_file())
+                .collect();
+
+            match git::diff(&root, &tracked, &untracked) {
+                Ok(text) if text.is_empty() => self.set_status("No changes".to_string()),
+                Ok(text) => {
+                    self.state.command_output = Some(CommandOutput {
+                        command: "git diff".to_string(),
+                        text,
+                        scroll: 0,
+                        diff: true,
+                    })
+                }
+                Err(e) => self.set_status(format!("{:#}", e)),
+            }
+        }
+    }
+
+    /// Links the marked (or selected) entries into the other panel's
+    /// directory.
+    pub fn link_chosen(&mut self, kind: LinkKind) {
+        if !self.state.dual_pane {
+            self.set_status(
+                "Links go into the other panel; turn on dual-pane mode first".to_string(),
+            );
+            return;
+        }
+
+        let dest_dir = PathBuf::from(self.view(self.other_panel()).current_dir());
+        let mut linked = 0;
+        for path in self.chosen_paths() {
+            if let Err(e) = ops::link_into(Path::new(&path), &dest_dir, kind) {
+                self.set_status(format!("{:#}", e));
+                self.reload_panels();
+                return;
+            }
+            linked += 1;
+        }
+        self.set_status(format!(
+            "Linked {} entries into {}",
+            linked,
+            dest_dir.display()
+        ));
+        self.reload_panels();
+    }
+
+    /// Asks before deleting the marked entries, or the selected one if none
+    /// are marked; `confirm_delete` does the deleting once it gets a yes.
+    pub fn delete_file(&mut self) {
+        if !self.get_active_view().marked_or_selected().is_empty() {
+            self.get_active_view_mut()
+                .start_input(EditingKind::ConfirmDelete, "");
+        }
+    }
+
+    pub fn confirm_delete(&mut self) {
+        let answer = self.text_input().value().trim().to_lowercase();
+        self.set_input_mode(InputMode::Normal);
+        if answer == "y" || answer == "yes" {
+            self.delete_chosen();
+        }
+    }
+
+    fn delete_chosen(&mut self) {
+        let items: Vec<Item> = self
+            .get_active_view()
+            .marked_or_selected()
+            .into_iter()
+            .cloned()
+            .collect();
+
+        let mut deleted = 0;
+        let mut error = None;
+        for item in &items {
+            match ops::remove_path(Path::new(&item.name)) {
+                Ok(()) => {
+                    let dir_sizes = &mut self.state.dir_sizes;
+                    match dir_sizes.get(&item.name) {
+                        Some(size) => dir_sizes.remove(&item.name, size),
+                        None if item.kind == ItemKind::Directory => {
+                            dir_sizes.invalidate(&item.name)
+                        }
+                        None => dir_sizes.remove(&item.name, item.size),
+                    }
+                    deleted += 1;
+                }
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        match (error, items.as_slice()) {
+            (Some(e), [_]) => self.set_status(format!("{:#}", e)),
+            (Some(e), _) => {
+                self.set_status(format!("Deleted {} of {}; {:#}", deleted, items.len(), e))
+            }
+            (None, [item]) => self.set_status(format!("Deleted {}", item.name)),
+            _ => self.set_status(format!("Deleted {} entries", deleted)),
+        }
+        self.get_active_view_mut().clear_marks();
+        self.reload_panels();
+    }
+
+    pub fn is_disk_usage(&self) -> bool {
+        self.get_active_view().is_disk_usage()
+    }
+
+    /// Switches the active panel in and out of the disk usage listing. Each
+    /// time it is switched on the sizes below the directory are rescanned.
+    pub fn toggle_disk_usage(&mut self) {
+        let disk_usage = !self.is_disk_usage();
+        if disk_usage {
+            let dir = self.current_dir().clone();
+            self.state.dir_sizes.invalidate(&dir);
+        }
+        if let Err(e) = self.get_active_view_mut().set_disk_usage(disk_usage) {
+            self.set_status(format!("{:#}", e));
+        }
+    }
+
+    /// Asks for the sizes the panels on screen are waiting for, dropping
+    /// any scan they no longer need, and fills in those already known.
+    pub fn update_dir_sizes(&mut self) {
+        let shows_action_panel = self.shows_action_panel();
+        let tab = &mut self.state.tabs[self.state.active_tab];
+        let mut views = vec![&mut tab.main_view];
+        if shows_action_panel {
+            views.push(&mut tab.action_view);
+        }
+
+        let wanted = views
+            .iter()
+            .flat_map(|view| view.pending_dir_sizes().cloned())
+            .collect();
+        self.state.dir_sizes.request(wanted);
+        for view in views {
+            view.apply_dir_sizes(&self.state.dir_sizes);
+        }
+    }
+
+    /// Asks for the git status of the directories on screen and shows what
+    /// is known so far.
+    pub fn update_git_statuses(&mut self) {
+        let shows_action_panel = self.shows_action_panel();
+        let tab = &mut self.state.tabs[self.state.active_tab];
+        let mut views = vec![&mut tab.main_view];
+        if shows_action_panel {
+            views.push(&mut tab.action_view);
+        }
+
+        let wanted = views
+            .iter()
+            .map(|view| view.current_dir().clone())
+            .filter(|dir| !dir.is_empty())
+            .collect();
+        self.state.git_statuses.request(wanted);
+        for view in views {
+            if let Some(repo) = self.state.git_statuses.get(view.current_dir()) {
+                view.apply_git_status(repo);
+            }
+        }
+    }
+}
+
+pub fn get_contents(path: &str) -> Result<Vec<Item>> {
+    let contents = WalkDir::new(path)
+        .sort_by_file_name()
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            let path = entry.path();
+            let modified_date = meta
+                .modified()
+                .map(DateTime::<Local>::from)
+                .unwrap_or_else(|_| Local.timestamp_opt(0, 0).unwrap());
+
+            Some(
+                Item::new()
+                    .with_name(&path.display().to_string())
+                    .with_size(meta.len())
+                    .with_mode(meta.permissions().mode())
+                    .with_modified_date(modified_date)
+                    .with_is_dir(path.is_dir())
+                    .with_kind(ItemKind::from_metadata(&meta, path))
+                    .with_link_target(
+                        Some(path)
+                            .filter(|_| meta.file_type().is_symlink())
+                            .and_then(|path| fs::read_link(path).ok()),
+                    ),
+            )
+        })
+        .collect();
+    Ok(contents)
+}
+
+/// Writes one name per line to a new file only we can read, in a private
+/// directory so no one else can plant or swap it.
+fn write_names(names: &[String]) -> Result<(TempDir, PathBuf)> {
+    let temp_dir = tempfile::Builder::new()
+        .prefix("walker-rename-")
+        .permissions(fs::Permissions::from_mode(0o700))
+        .tempdir()
+        .context("failed to create a temporary directory")?;
+    let file = temp_dir.path().join("names.txt");
+    let mut contents = names.join("\n");
+    contents.push('\n');
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&file)
+        .and_then(|mut out| out.write_all(contents.as_bytes()))
+        .with_context(|| format!("failed to write {}", file.display()))?;
+    Ok((temp_dir, file))
 }
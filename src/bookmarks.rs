@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+const BOOKMARKS_FILE: &str = "bookmarks.toml";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Bookmark {
+    pub key: char,
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct BookmarkFile {
+    #[serde(default, rename = "bookmark")]
+    bookmarks: Vec<Bookmark>,
+}
+
+/// Directories saved under a single key, kept sorted by key and written back
+/// to the data dir after every change.
+#[derive(Clone, Debug, Default)]
+pub struct Bookmarks {
+    entries: Vec<Bookmark>,
+    file: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    pub fn load() -> Result<Self> {
+        let file = config::data_dir().map(|dir| dir.join(BOOKMARKS_FILE));
+        let entries = match &file {
+            Some(file) if file.exists() => {
+                let contents = std::fs::read_to_string(file)
+                    .with_context(|| format!("failed to read {}", file.display()))?;
+                toml::from_str::<BookmarkFile>(&contents)
+                    .with_context(|| format!("failed to parse {}", file.display()))?
+                    .bookmarks
+            }
+            _ => vec![],
+        };
+
+        Ok(Self { entries, file })
+    }
+
+    pub fn entries(&self) -> &[Bookmark] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: char) -> Option<&Bookmark> {
+        self.entries.iter().find(|b| b.key == key)
+    }
+
+    /// Saves `dir` under `key`, replacing any bookmark already using it.
+    pub fn set(&mut self, key: char, dir: &str) -> Result<()> {
+        let name = Path::new(dir)
+            .file_name()
+            .map_or(dir.to_string(), |n| n.to_string_lossy().to_string());
+        let bookmark = Bookmark {
+            key,
+            name,
+            path: dir.to_string(),
+        };
+
+        match self.entries.iter_mut().find(|b| b.key == key) {
+            Some(existing) => *existing = bookmark,
+            None => {
+                self.entries.push(bookmark);
+                self.entries.sort_by_key(|b| b.key);
+            }
+        }
+        self.save()
+    }
+
+    pub fn rename(&mut self, idx: usize, name: &str) -> Result<()> {
+        if let Some(bookmark) = self.entries.get_mut(idx) {
+            bookmark.name = name.to_string();
+        }
+        self.save()
+    }
+
+    pub fn remove(&mut self, idx: usize) -> Result<()> {
+        if idx < self.entries.len() {
+            self.entries.remove(idx);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let contents = toml::to_string(&BookmarkFile {
+            bookmarks: self.entries.clone(),
+        })?;
+        std::fs::write(file, contents)
+            .with_context(|| format!("failed to write {}", file.display()))
+    }
+}
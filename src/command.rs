@@ -0,0 +1,351 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::listing::SortKind;
+
+/// Command names, in the order completion offers them.
+const COMMANDS: &[&str] = &[
+    "cd", "mkdir", "touch", "sort", "filter", "set", "bookmark", "q", "quit",
+];
+const SORTS: &[&str] = &["name", "size", "modified", "extension"];
+const SETTINGS: &[&str] = &[
+    "hidden",
+    "nohidden",
+    "reverse",
+    "noreverse",
+    "dirsfirst",
+    "nodirsfirst",
+    "dualpane",
+    "nodualpane",
+    "physical",
+    "nophysical",
+    "gitignored",
+    "nogitignored",
+    "sort=",
+    "theme=",
+];
+
+/// A line typed at the `:` prompt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Cd(PathBuf),
+    Mkdir(PathBuf),
+    Touch(PathBuf),
+    Sort(SortKind),
+    Filter(Option<String>),
+    Set(Setting),
+    Bookmark(char),
+    Quit,
+}
+
+/// An option changed with `:set`, vim style: `:set hidden`, `:set nohidden`,
+/// `:set sort=size`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Hidden(bool),
+    Reverse(bool),
+    DirsFirst(bool),
+    DualPane(bool),
+    Physical(bool),
+    GitIgnored(bool),
+    Sort(SortKind),
+    Theme(String),
+}
+
+impl Command {
+    /// Parses `line`, resolving paths against `cwd`.
+    pub fn parse(line: &str, cwd: &str) -> Result<Self> {
+        let line = line.trim().trim_start_matches(':');
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+
+        let command = match name {
+            "cd" => Command::Cd(resolve_path(cwd, if arg.is_empty() { "~" } else { arg })),
+            "mkdir" => Command::Mkdir(resolve_path(cwd, required(name, arg)?)),
+            "touch" => Command::Touch(resolve_path(cwd, required(name, arg)?)),
+            "sort" => Command::Sort(required(name, arg)?.parse()?),
+            "filter" => Command::Filter(Some(arg.to_string()).filter(|f| !f.is_empty())),
+            "set" => Command::Set(Setting::parse(required(name, arg)?)?),
+            "bookmark" => {
+                let mut chars = required(name, arg)?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(key), None) => Command::Bookmark(key),
+                    _ => bail!("bookmark: expected a single letter"),
+                }
+            }
+            "q" | "quit" => Command::Quit,
+            "" => bail!("no command"),
+            _ => bail!("unknown command '{}'", name),
+        };
+        Ok(command)
+    }
+}
+
+impl Setting {
+    fn parse(arg: &str) -> Result<Self> {
+        if let Some((name, value)) = arg.split_once('=') {
+            return match name {
+                "sort" => Ok(Setting::Sort(value.parse()?)),
+                "theme" => Ok(Setting::Theme(value.to_string())),
+                _ => bail!("set: unknown option '{}'", name),
+            };
+        }
+
+        let (name, on) = match arg.strip_prefix("no") {
+            Some(name) => (name, false),
+            None => (arg, true),
+        };
+        match name {
+            "hidden" => Ok(Setting::Hidden(on)),
+            "reverse" => Ok(Setting::Reverse(on)),
+            "dirsfirst" => Ok(Setting::DirsFirst(on)),
+            "dualpane" => Ok(Setting::DualPane(on)),
+            "physical" => Ok(Setting::Physical(on)),
+            "gitignored" => Ok(Setting::GitIgnored(on)),
+            _ => bail!("set: unknown option '{}'", arg),
+        }
+    }
+}
+
+fn required<'a>(command: &str, arg: &'a str) -> Result<&'a str> {
+    if arg.is_empty() {
+        Err(anyhow!("{}: missing argument", command))
+    } else {
+        Ok(arg)
+    }
+}
+
+/// `path` relative to `cwd`, with `~` expanded and `.` and `..` folded away
+/// without resolving symlinks.
+pub fn resolve_path(cwd: &str, path: &str) -> PathBuf {
+    let path = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = std::env::var("HOME").unwrap_or_default();
+            format!("{}{}", home, rest)
+        }
+        _ => path.to_string(),
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in Path::new(cwd).join(path).components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+/// Completes the word before the end of `line`: the command name, a sort
+/// kind or setting, or a path relative to `cwd`. Returns the new line, or
+/// `None` when nothing matches.
+pub fn complete(line: &str, cwd: &str) -> Option<String> {
+    let (name, arg) = match line.split_once(' ') {
+        Some((name, arg)) => (name, arg.trim_start()),
+        None => {
+            let completed = complete_word(line, COMMANDS.iter().map(|c| c.to_string()))?;
+            return Some(if COMMANDS.contains(&completed.as_str()) {
+                format!("{} ", completed)
+            } else {
+                completed
+            });
+        }
+    };
+
+    let completed = match name {
+        "cd" => complete_path(arg, cwd, true)?,
+        "mkdir" | "touch" => complete_path(arg, cwd, false)?,
+        "sort" => complete_word(arg, SORTS.iter().map(|s| s.to_string()))?,
+        "set" => complete_word(arg, SETTINGS.iter().map(|s| s.to_string()))?,
+        _ => return None,
+    };
+    Some(format!("{} {}", name, completed))
+}
+
+/// The longest common prefix of the `candidates` starting with `word`.
+pub fn complete_word(word: &str, candidates: impl Iterator<Item = String>) -> Option<String> {
+    candidates
+        .filter(|c| c.starts_with(word))
+        .reduce(|prefix, c| common_prefix(&prefix, &c).to_string())
+}
+
+/// Completes the last component of `arg` from the entries of its directory,
+/// adding a `/` once a single directory is left.
+fn complete_path(arg: &str, cwd: &str, dirs_only: bool) -> Option<String> {
+    let (dir_part, prefix) = match arg.rfind('/') {
+        Some(idx) => arg.split_at(idx + 1),
+        None => ("", arg),
+    };
+    let dir = resolve_path(cwd, if dir_part.is_empty() { "." } else { dir_part });
+
+    let mut matches: Vec<(String, bool)> = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            (name, entry.path().is_dir())
+        })
+        .filter(|(name, is_dir)| (*is_dir || !dirs_only) && name.starts_with(prefix))
+        .filter(|(name, _)| prefix.starts_with('.') || !name.starts_with('.'))
+        .collect();
+    matches.sort();
+
+    let completed = match matches.as_slice() {
+        [] => return None,
+        [(name, true)] => format!("{}/", name),
+        [(name, false)] => name.clone(),
+        _ => complete_word(prefix, matches.into_iter().map(|(name, _)| name))?,
+    };
+    Some(format!("{}{}", dir_part, completed))
+}
+
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((idx, _), _)| idx);
+    &a[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Command {
+        Command::parse(line, "/work").unwrap()
+    }
+
+    fn parse_error(line: &str) -> String {
+        Command::parse(line, "/work").unwrap_err().to_string()
+    }
+
+    fn home() -> PathBuf {
+        PathBuf::from(std::env::var("HOME").unwrap_or_default())
+    }
+
+    #[test]
+    fn parse_reads_each_command() {
+        assert_eq!(parse("cd src"), Command::Cd(PathBuf::from("/work/src")));
+        assert_eq!(parse(":cd"), Command::Cd(home()));
+        assert_eq!(
+            parse("mkdir new"),
+            Command::Mkdir(PathBuf::from("/work/new"))
+        );
+        assert_eq!(parse("touch ../a b"), Command::Touch(PathBuf::from("/a b")));
+        assert_eq!(parse("sort ext"), Command::Sort(SortKind::Extension));
+        assert_eq!(
+            parse("filter  .rs "),
+            Command::Filter(Some(".rs".to_string()))
+        );
+        assert_eq!(parse("filter"), Command::Filter(None));
+        assert_eq!(parse("bookmark a"), Command::Bookmark('a'));
+        assert_eq!(parse("q"), Command::Quit);
+        assert_eq!(parse("quit"), Command::Quit);
+    }
+
+    #[test]
+    fn parse_reads_settings() {
+        assert_eq!(parse("set hidden"), Command::Set(Setting::Hidden(true)));
+        assert_eq!(
+            parse("set nodualpane"),
+            Command::Set(Setting::DualPane(false))
+        );
+        assert_eq!(
+            parse("set gitignored"),
+            Command::Set(Setting::GitIgnored(true))
+        );
+        assert_eq!(
+            parse("set sort=size"),
+            Command::Set(Setting::Sort(SortKind::Size))
+        );
+        assert_eq!(
+            parse("set theme=light"),
+            Command::Set(Setting::Theme("light".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_reports_argument_errors() {
+        assert_eq!(parse_error(""), "no command");
+        assert_eq!(parse_error("rm x"), "unknown command 'rm'");
+        assert_eq!(parse_error("mkdir"), "mkdir: missing argument");
+        assert_eq!(parse_error("touch "), "touch: missing argument");
+        assert_eq!(parse_error("set"), "set: missing argument");
+        assert_eq!(
+            parse_error("bookmark ab"),
+            "bookmark: expected a single letter"
+        );
+        assert_eq!(parse_error("set colour"), "set: unknown option 'colour'");
+        assert_eq!(parse_error("set width=3"), "set: unknown option 'width'");
+        assert_eq!(
+            parse_error("sort colour"),
+            "unknown sort 'colour' (expected name, size, modified or extension)"
+        );
+    }
+
+    #[test]
+    fn resolve_path_expands_home_and_folds_dots() {
+        assert_eq!(resolve_path("/work", "~"), home());
+        assert_eq!(resolve_path("/work", "~/notes"), home().join("notes"));
+        assert_eq!(resolve_path("/work", "~user"), PathBuf::from("/work/~user"));
+        assert_eq!(
+            resolve_path("/work/src", "../docs/./a"),
+            PathBuf::from("/work/docs/a")
+        );
+        assert_eq!(resolve_path("/work", "/etc/../tmp"), PathBuf::from("/tmp"));
+        assert_eq!(resolve_path("/", ".."), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn complete_word_finds_the_common_prefix() {
+        let words = || {
+            ["dirsfirst", "dualpane", "nodualpane"]
+                .iter()
+                .map(|w| w.to_string())
+        };
+        assert_eq!(complete_word("d", words()), Some("d".to_string()));
+        assert_eq!(complete_word("du", words()), Some("dualpane".to_string()));
+        assert_eq!(complete_word("", words()), Some(String::new()));
+        assert_eq!(complete_word("x", words()), None);
+    }
+
+    #[test]
+    fn complete_offers_commands_and_arguments() {
+        assert_eq!(complete("mk", "/"), Some("mkdir ".to_string()));
+        assert_eq!(complete("q", "/"), Some("q ".to_string()));
+        assert_eq!(complete("sort m", "/"), Some("sort modified".to_string()));
+        assert_eq!(complete("set the", "/"), Some("set theme=".to_string()));
+        assert_eq!(complete("filter a", "/"), None);
+    }
+
+    #[test]
+    fn complete_fills_in_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().to_str().unwrap();
+        fs::create_dir_all(dir.path().join("src/bin")).unwrap();
+        fs::create_dir(dir.path().join("secrets")).unwrap();
+        fs::create_dir(dir.path().join(".hidden")).unwrap();
+        fs::write(dir.path().join("setup.sh"), "").unwrap();
+
+        assert_eq!(complete("cd s", cwd), Some("cd s".to_string()));
+        assert_eq!(complete("cd sr", cwd), Some("cd src/".to_string()));
+        assert_eq!(complete("cd src/b", cwd), Some("cd src/bin/".to_string()));
+        assert_eq!(
+            complete("touch set", cwd),
+            Some("touch setup.sh".to_string())
+        );
+        assert_eq!(complete("cd set", cwd), None);
+        assert_eq!(complete("cd .h", cwd), Some("cd .hidden/".to_string()));
+        assert_eq!(complete("cd missing/", cwd), None);
+    }
+}
@@ -0,0 +1,663 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, Metadata},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{bail, Context, Result};
+use similar::{ChangeTag, TextDiff};
+use tempfile::NamedTempFile;
+use tui::widgets::TableState;
+
+use crate::ops;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RowKind {
+    Same,
+    Removed,
+    Added,
+    /// The left line was replaced by the right one.
+    Changed,
+}
+
+/// One row of a side-by-side diff: a line from either file, or both when
+/// they are the same or one replaces the other.
+#[derive(Clone, Debug)]
+pub struct SideBySideRow {
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub kind: RowKind,
+}
+
+/// The differences between the files selected in the two panels.
+#[derive(Clone, Debug)]
+pub struct FileDiff {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    pub unified: String,
+    /// `None` between hunks.
+    pub rows: Vec<Option<SideBySideRow>>,
+    pub side_by_side: bool,
+    pub scroll: u16,
+}
+
+impl FileDiff {
+    /// Diffs two text files, or returns `None` when they are the same.
+    pub fn new(left: &Path, right: &Path) -> Result<Option<Self>> {
+        let left_text = read_text(left)?;
+        let right_text = read_text(right)?;
+        if left_text == right_text {
+            return Ok(None);
+        }
+
+        let diff = TextDiff::from_lines(&left_text, &right_text);
+        let unified = diff
+            .unified_diff()
+            .context_radius(3)
+            .header(&left.display().to_string(), &right.display().to_string())
+            .to_string();
+
+        let mut rows = vec![];
+        for (i, group) in diff.grouped_ops(3).iter().enumerate() {
+            if i > 0 {
+                rows.push(None);
+            }
+            let mut deleted = vec![];
+            let mut inserted = vec![];
+            for op in group {
+                for change in diff.iter_changes(op) {
+                    let line = change.value().trim_end_matches('\n').to_string();
+                    match change.tag() {
+                        ChangeTag::Delete => deleted.push(line),
+                        ChangeTag::Insert => inserted.push(line),
+                        ChangeTag::Equal => {
+                            pair_changes(&mut rows, &mut deleted, &mut inserted);
+                            rows.push(Some(SideBySideRow {
+                                left: Some(line.clone()),
+                                right: Some(line),
+                                kind: RowKind::Same,
+                            }));
+                        }
+                    }
+                }
+            }
+            pair_changes(&mut rows, &mut deleted, &mut inserted);
+        }
+
+        Ok(Some(Self {
+            left: left.to_path_buf(),
+            right: right.to_path_buf(),
+            unified,
+            rows,
+            side_by_side: false,
+            scroll: 0,
+        }))
+    }
+
+    pub fn line_count(&self) -> usize {
+        if self.side_by_side {
+            self.rows.len()
+        } else {
+            self.unified.lines().count()
+        }
+    }
+}
+
+/// Puts a run of removed lines next to the lines that replaced them, like
+/// `diff --side-by-side`.
+fn pair_changes(
+    rows: &mut Vec<Option<SideBySideRow>>,
+    deleted: &mut Vec<String>,
+    inserted: &mut Vec<String>,
+) {
+    let count = deleted.len().max(inserted.len());
+    let mut deleted = deleted.drain(..);
+    let mut inserted = inserted.drain(..);
+    for _ in 0..count {
+        let (left, right) = (deleted.next(), inserted.next());
+        let kind = match (&left, &right) {
+            (Some(_), None) => RowKind::Removed,
+            (None, Some(_)) => RowKind::Added,
+            _ => RowKind::Changed,
+        };
+        rows.push(Some(SideBySideRow { left, right, kind }));
+    }
+}
+
+fn read_text(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(_) => bail!("{} is not a text file", path.display()),
+    }
+}
+
+/// How an entry of the left directory relates to the right one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Difference {
+    OnlyLeft,
+    OnlyRight,
+    LeftNewer,
+    RightNewer,
+    /// Different but neither is newer, or one is a file and the other a
+    /// directory; sync leaves these alone.
+    Different,
+}
+
+impl Difference {
+    pub fn label(self) -> &'static str {
+        match self {
+            Difference::OnlyLeft => "only left",
+            Difference::OnlyRight => "only right",
+            Difference::LeftNewer => "left newer",
+            Difference::RightNewer => "right newer",
+            Difference::Different => "different",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub name: String,
+    pub difference: Difference,
+    pub left: Option<Metadata>,
+    pub right: Option<Metadata>,
+}
+
+/// Which way `sync` copies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SyncDirection {
+    ToRight,
+    ToLeft,
+    Both,
+}
+
+/// The entries that differ between two directories, without descending into
+/// the subdirectories both have.
+#[derive(Clone, Debug)]
+pub struct DirComparison {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    /// Compare the contents of files the same size instead of trusting
+    /// their modification times.
+    pub by_content: bool,
+    pub entries: Vec<Entry>,
+    pub identical: usize,
+    pub table_state: TableState,
+    /// A sync waiting for a yes, as it overwrites files.
+    pub pending_sync: Option<SyncDirection>,
+}
+
+impl DirComparison {
+    pub fn new(left: &Path, right: &Path, by_content: bool) -> Result<Self> {
+        let mut comparison = Self {
+            left: left.to_path_buf(),
+            right: right.to_path_buf(),
+            by_content,
+            entries: vec![],
+            identical: 0,
+            table_state: TableState::default(),
+            pending_sync: None,
+        };
+        comparison.refresh()?;
+        Ok(comparison)
+    }
+
+    /// Compares the directories again, e.g. after a sync.
+    pub fn refresh(&mut self) -> Result<()> {
+        let mut names = BTreeSet::new();
+        for dir in [&self.left, &self.right] {
+            for entry in
+                fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+            {
+                names.insert(entry?.file_name().to_string_lossy().to_string());
+            }
+        }
+
+        self.entries.clear();
+        self.identical = 0;
+        for name in names {
+            let left = entry_metadata(&self.left.join(&name));
+            let right = entry_metadata(&self.right.join(&name));
+            let difference = match (&left, &right) {
+                (Some(_), None) => Some(Difference::OnlyLeft),
+                (None, Some(_)) => Some(Difference::OnlyRight),
+                (Some(l), Some(r)) => self.compare(&name, l, r),
+                (None, None) => None,
+            };
+            match difference {
+                Some(difference) => self.entries.push(Entry {
+                    name,
+                    difference,
+                    left,
+                    right,
+                }),
+                None => self.identical += 1,
+            }
+        }
+
+        let selected = self.table_state.selected().unwrap_or(0);
+        self.table_state.select(if self.entries.is_empty() {
+            None
+        } else {
+            Some(selected.min(self.entries.len() - 1))
+        });
+        Ok(())
+    }
+
+    fn compare(&self, name: &str, left: &Metadata, right: &Metadata) -> Option<Difference> {
+        // Only dangling links are left as symlinks; they match nothing but a
+        // link to the same place.
+        match (left.is_symlink(), right.is_symlink()) {
+            (false, false) => {}
+            (true, true) => {
+                let left = fs::read_link(self.left.join(name)).ok();
+                let right = fs::read_link(self.right.join(name)).ok();
+                return (left.is_none() || left != right).then_some(Difference::Different);
+            }
+            _ => return Some(Difference::Different),
+        }
+        if left.is_dir() && right.is_dir() {
+            return None;
+        }
+        if left.is_dir() != right.is_dir() {
+            return Some(Difference::Different);
+        }
+
+        let same = if left.len() != right.len() {
+            false
+        } else if self.by_content {
+            same_contents(&self.left.join(name), &self.right.join(name)).unwrap_or(false)
+        } else {
+            modified(left) == modified(right)
+        };
+        if same {
+            return None;
+        }
+
+        Some(match modified(left).cmp(&modified(right)) {
+            std::cmp::Ordering::Greater => Difference::LeftNewer,
+            std::cmp::Ordering::Less => Difference::RightNewer,
+            std::cmp::Ordering::Equal => Difference::Different,
+        })
+    }
+
+    pub fn move_selection(&mut self, down: bool) {
+        let count = self.entries.len();
+        if count == 0 {
+            return;
+        }
+        let selected = self.table_state.selected().unwrap_or(0);
+        self.table_state.select(Some(if down {
+            (selected + 1).min(count - 1)
+        } else {
+            selected.saturating_sub(1)
+        }));
+    }
+
+    /// The entries `sync` would copy, each with whether it goes to the
+    /// right.
+    fn planned(&self, direction: SyncDirection) -> impl Iterator<Item = (&Entry, bool)> {
+        let to_right = direction != SyncDirection::ToLeft;
+        let to_left = direction != SyncDirection::ToRight;
+        self.entries
+            .iter()
+            .filter_map(move |entry| match entry.difference {
+                Difference::OnlyLeft | Difference::LeftNewer if to_right => Some((entry, true)),
+                Difference::OnlyRight | Difference::RightNewer if to_left => Some((entry, false)),
+                _ => None,
+            })
+    }
+
+    /// What `sync` would do, as a question to confirm, or `None` when there
+    /// is nothing to copy.
+    pub fn sync_question(&self, direction: SyncDirection) -> Option<String> {
+        let mut parts = vec![];
+        for (to_right, side) in [(true, "to the right"), (false, "to the left")] {
+            let (mut copies, mut overwrites) = (0, 0);
+            for (entry, _) in self
+                .planned(direction)
+                .filter(|(_, right)| *right == to_right)
+            {
+                copies += 1;
+                if entry.left.is_some() && entry.right.is_some() {
+                    overwrites += 1;
+                }
+            }
+            if copies > 0 {
+                parts.push(format!(
+                    "{} entries {} ({} overwritten)",
+                    copies, side, overwrites
+                ));
+            }
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(format!("Copy {}? (y/N)", parts.join(" and ")))
+        }
+    }
+
+    /// Copies what is missing or older on the other side, keeping
+    /// modification times so the entries compare the same afterwards.
+    /// Returns how many entries were copied.
+    pub fn sync(&self, direction: SyncDirection) -> Result<usize> {
+        let mut copied = 0;
+        for (entry, to_right) in self.planned(direction) {
+            let (from, to) = if to_right {
+                (&self.left, &self.right)
+            } else {
+                (&self.right, &self.left)
+            };
+            copy_entry(&from.join(&entry.name), to)?;
+            copied += 1;
+        }
+        Ok(copied)
+    }
+}
+
+/// What `path` is, following symlinks, or the link itself when it dangles.
+fn entry_metadata(path: &Path) -> Option<Metadata> {
+    fs::metadata(path)
+        .or_else(|_| fs::symlink_metadata(path))
+        .ok()
+}
+
+/// Copies `src` into `dest_dir`, replacing an older entry of the same name
+/// (rather than writing through it, should it be a symlink).
+fn copy_entry(src: &Path, dest_dir: &Path) -> Result<()> {
+    let dest = match src.file_name() {
+        Some(name) => dest_dir.join(name),
+        None => bail!("{} has no file name", src.display()),
+    };
+    if fs::symlink_metadata(&dest).is_ok() {
+        return replace_file(src, &dest, dest_dir)
+            .with_context(|| format!("failed to copy {} to {}", src.display(), dest.display()));
+    }
+
+    ops::copy_into(src, dest_dir)?;
+    let meta = fs::symlink_metadata(src)?;
+    if meta.is_file() {
+        File::options()
+            .write(true)
+            .open(&dest)
+            .and_then(|file| file.set_modified(modified(&meta)))
+            .with_context(|| {
+                format!("failed to set the modification time of {}", dest.display())
+            })?;
+    }
+    Ok(())
+}
+
+/// Writes a copy of `src` next to `dest` and renames it over `dest`.
+fn replace_file(src: &Path, dest: &Path, dest_dir: &Path) -> io::Result<()> {
+    let mut source = File::open(src)?;
+    let meta = source.metadata()?;
+    let mut temp = NamedTempFile::new_in(dest_dir)?;
+    io::copy(&mut source, &mut temp)?;
+    temp.as_file().set_permissions(meta.permissions())?;
+    temp.as_file().set_modified(modified(&meta))?;
+    temp.persist(dest).map(|_| ()).map_err(|e| e.error)
+}
+
+fn modified(meta: &Metadata) -> SystemTime {
+    meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Whether two files hold the same bytes, reading both only as far as the
+/// first difference.
+fn same_contents(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut buf_a = [0; 8192];
+    let mut buf_b = [0; 8192];
+    loop {
+        let read = a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const OLD: SystemTime = SystemTime::UNIX_EPOCH;
+
+    fn new_time() -> SystemTime {
+        OLD + Duration::from_secs(1_000_000)
+    }
+
+    fn write(dir: &Path, name: &str, contents: &str, time: SystemTime) {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    fn dirs() -> (TempDir, PathBuf, PathBuf) {
+        let temp = tempfile::tempdir().unwrap();
+        let (left, right) = (temp.path().join("left"), temp.path().join("right"));
+        fs::create_dir(&left).unwrap();
+        fs::create_dir(&right).unwrap();
+        (temp, left, right)
+    }
+
+    fn differences(comparison: &DirComparison) -> Vec<(&str, Difference)> {
+        comparison
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.difference))
+            .collect()
+    }
+
+    #[test]
+    fn compare_classifies_entries() {
+        let (_temp, left, right) = dirs();
+        write(&left, "left_only", "", OLD);
+        write(&right, "right_only", "", OLD);
+        write(&left, "left_newer", "new", new_time());
+        write(&right, "left_newer", "old", OLD);
+        write(&left, "right_newer", "old", OLD);
+        write(&right, "right_newer", "new!", new_time());
+        write(&left, "same", "same", OLD);
+        write(&right, "same", "same", OLD);
+        write(&left, "kind", "", OLD);
+        fs::create_dir(right.join("kind")).unwrap();
+        fs::create_dir(left.join("both_dirs")).unwrap();
+        fs::create_dir(right.join("both_dirs")).unwrap();
+
+        let comparison = DirComparison::new(&left, &right, false).unwrap();
+
+        assert_eq!(
+            differences(&comparison),
+            vec![
+                ("kind", Difference::Different),
+                ("left_newer", Difference::LeftNewer),
+                ("left_only", Difference::OnlyLeft),
+                ("right_newer", Difference::RightNewer),
+                ("right_only", Difference::OnlyRight),
+            ]
+        );
+        assert_eq!(comparison.identical, 2);
+    }
+
+    #[test]
+    fn compare_reports_dangling_links() {
+        use std::os::unix::fs::symlink;
+
+        let (_temp, left, right) = dirs();
+        symlink("missing", left.join("left_only")).unwrap();
+        symlink("missing", left.join("against_file")).unwrap();
+        write(&right, "against_file", "", OLD);
+        symlink("missing", left.join("same_target")).unwrap();
+        symlink("missing", right.join("same_target")).unwrap();
+        symlink("missing", left.join("other_target")).unwrap();
+        symlink("elsewhere", right.join("other_target")).unwrap();
+
+        let comparison = DirComparison::new(&left, &right, false).unwrap();
+
+        assert_eq!(
+            differences(&comparison),
+            vec![
+                ("against_file", Difference::Different),
+                ("left_only", Difference::OnlyLeft),
+                ("other_target", Difference::Different),
+            ]
+        );
+        assert_eq!(comparison.identical, 1);
+
+        comparison.sync(SyncDirection::ToRight).unwrap();
+        assert_eq!(
+            fs::read_link(right.join("left_only")).unwrap(),
+            Path::new("missing")
+        );
+    }
+
+    #[test]
+    fn compare_by_content_looks_past_times() {
+        let (_temp, left, right) = dirs();
+        write(&left, "touched", "same", new_time());
+        write(&right, "touched", "same", OLD);
+        write(&left, "edited", "abc", OLD);
+        write(&right, "edited", "xyz", OLD);
+
+        let by_time = DirComparison::new(&left, &right, false).unwrap();
+        let by_content = DirComparison::new(&left, &right, true).unwrap();
+
+        assert_eq!(
+            differences(&by_time),
+            vec![("touched", Difference::LeftNewer)]
+        );
+        assert_eq!(
+            differences(&by_content),
+            vec![("edited", Difference::Different)]
+        );
+    }
+
+    #[test]
+    fn sync_copies_missing_and_older_entries_keeping_times() {
+        let (_temp, left, right) = dirs();
+        write(&left, "new", "left", new_time());
+        write(&left, "updated", "newer", new_time());
+        write(&right, "updated", "older", OLD);
+        write(&right, "back", "right", OLD);
+        write(&left, "conflict", "abc", OLD);
+        write(&right, "conflict", "xyz", OLD);
+        let mut comparison = DirComparison::new(&left, &right, true).unwrap();
+
+        assert_eq!(
+            comparison.sync_question(SyncDirection::Both).unwrap(),
+            "Copy 2 entries to the right (1 overwritten) and 1 entries to the left (0 overwritten)? (y/N)"
+        );
+        assert_eq!(comparison.sync(SyncDirection::Both).unwrap(), 3);
+
+        assert_eq!(fs::read_to_string(right.join("updated")).unwrap(), "newer");
+        assert_eq!(fs::read_to_string(left.join("back")).unwrap(), "right");
+        assert_eq!(fs::read_to_string(right.join("conflict")).unwrap(), "xyz");
+        comparison.refresh().unwrap();
+        assert_eq!(
+            differences(&comparison),
+            vec![("conflict", Difference::Different)]
+        );
+        assert_eq!(comparison.sync_question(SyncDirection::Both), None);
+    }
+
+    #[test]
+    fn sync_in_one_direction_leaves_the_other_alone() {
+        let (_temp, left, right) = dirs();
+        write(&left, "to_right", "", OLD);
+        write(&right, "to_left", "", OLD);
+        let comparison = DirComparison::new(&left, &right, false).unwrap();
+
+        assert_eq!(comparison.sync(SyncDirection::ToRight).unwrap(), 1);
+
+        assert!(right.join("to_right").exists());
+        assert!(!left.join("to_left").exists());
+    }
+
+    #[test]
+    fn sync_replaces_symlinks_instead_of_following_them() {
+        let (temp, left, right) = dirs();
+        let outside = temp.path().join("outside");
+        write(temp.path(), "outside", "untouched", OLD);
+        write(&left, "file", "newer", new_time());
+        std::os::unix::fs::symlink(&outside, right.join("file")).unwrap();
+        let comparison = DirComparison::new(&left, &right, false).unwrap();
+
+        assert_eq!(comparison.sync(SyncDirection::ToRight).unwrap(), 1);
+
+        assert_eq!(fs::read_to_string(&outside).unwrap(), "untouched");
+        let meta = fs::symlink_metadata(right.join("file")).unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.modified().unwrap(), new_time());
+    }
+
+    #[test]
+    fn file_diff_is_none_for_identical_files() {
+        let (_temp, left, right) = dirs();
+        write(&left, "file", "a\nb\n", OLD);
+        write(&right, "file", "a\nb\n", new_time());
+
+        assert!(FileDiff::new(&left.join("file"), &right.join("file"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn file_diff_pairs_replaced_lines_side_by_side() {
+        let (_temp, left, right) = dirs();
+        write(&left, "file", "same\nold 1\nold 2\nend\n", OLD);
+        write(&right, "file", "same\nnew 1\nend\nadded\n", OLD);
+
+        let diff = FileDiff::new(&left.join("file"), &right.join("file"))
+            .unwrap()
+            .unwrap();
+
+        let rows: Vec<_> = diff
+            .rows
+            .iter()
+            .flatten()
+            .map(|row| (row.left.as_deref(), row.right.as_deref(), row.kind))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (Some("same"), Some("same"), RowKind::Same),
+                (Some("old 1"), Some("new 1"), RowKind::Changed),
+                (Some("old 2"), None, RowKind::Removed),
+                (Some("end"), Some("end"), RowKind::Same),
+                (None, Some("added"), RowKind::Added),
+            ]
+        );
+        assert!(diff.unified.contains("-old 1\n-old 2\n+new 1\n"));
+    }
+
+    #[test]
+    fn pair_changes_puts_extra_insertions_last() {
+        let mut rows = vec![];
+        let mut deleted = vec!["a".to_string()];
+        let mut inserted = vec!["b".to_string(), "c".to_string()];
+
+        pair_changes(&mut rows, &mut deleted, &mut inserted);
+
+        let kinds: Vec<_> = rows.iter().flatten().map(|row| row.kind).collect();
+        assert_eq!(kinds, vec![RowKind::Changed, RowKind::Added]);
+        assert!(deleted.is_empty() && inserted.is_empty());
+    }
+}
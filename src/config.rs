@@ -0,0 +1,85 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+const APP_DIR: &str = "walker";
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub theme: Option<String>,
+    pub ls_colors: Option<bool>,
+    pub dual_pane: Option<bool>,
+    pub physical_paths: Option<bool>,
+    pub git_ignored: Option<bool>,
+    pub themes: HashMap<String, ThemeConfig>,
+}
+
+/// A user defined theme. Every style is a spec string such as
+/// `"bold blue"` or `"black on #007dff"`; unset styles fall back to `base`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub base: Option<String>,
+    pub header: Option<String>,
+    pub active_tab: Option<String>,
+    pub highlight: Option<String>,
+    pub marked: Option<String>,
+    pub border: Option<String>,
+    pub file: Option<String>,
+    pub directory: Option<String>,
+    pub executable: Option<String>,
+    pub symlink: Option<String>,
+    pub broken_link: Option<String>,
+    pub socket: Option<String>,
+    pub fifo: Option<String>,
+    pub device: Option<String>,
+    pub git_modified: Option<String>,
+    pub git_staged: Option<String>,
+    pub git_untracked: Option<String>,
+    pub git_ignored: Option<String>,
+    pub git_conflicted: Option<String>,
+    pub diff_added: Option<String>,
+    pub diff_removed: Option<String>,
+    pub diff_hunk: Option<String>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        match config_dir() {
+            Some(dir) => Self::load_from(&dir.join(CONFIG_FILE)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &std::path::Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("failed to parse config {}", path.display()))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/walker`, falling back to `~/.config/walker`.
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_DATA_HOME/walker`, falling back to `~/.local/share/walker`.
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn xdg_dir(var: &str, home_fallback: &str) -> Option<PathBuf> {
+    let base = std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(home_fallback)))?;
+    Some(base.join(APP_DIR))
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+type SizeMap = Arc<Mutex<HashMap<String, u64>>>;
+
+/// A background scan of a set of directories.
+#[derive(Debug)]
+struct Job {
+    dirs: HashSet<String>,
+    cancel: Arc<AtomicBool>,
+}
+
+/// Recursive directory sizes, computed on a background thread and cached
+/// for every directory the scan passes through, so drilling into a scanned
+/// directory needs no further work.
+#[derive(Debug, Default)]
+pub struct DirSizes {
+    sizes: SizeMap,
+    job: Option<Job>,
+}
+
+impl DirSizes {
+    pub fn get(&self, dir: &str) -> Option<u64> {
+        self.sizes.lock().ok()?.get(dir).copied()
+    }
+
+    /// Makes sure the sizes of `dirs` are being computed. A running scan
+    /// for a different set of directories is cancelled; whatever it already
+    /// finished stays cached.
+    pub fn request(&mut self, dirs: HashSet<String>) {
+        let missing: HashSet<String> = dirs
+            .into_iter()
+            .filter(|dir| self.get(dir).is_none())
+            .collect();
+
+        if let Some(job) = &self.job {
+            if missing.is_subset(&job.dirs) && !job.cancel.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+        self.cancel();
+        if missing.is_empty() {
+            return;
+        }
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let sizes = Arc::clone(&self.sizes);
+        let worker_cancel = Arc::clone(&cancel);
+        let worker_dirs: Vec<String> = missing.iter().cloned().collect();
+        thread::spawn(move || {
+            for dir in worker_dirs {
+                if scan(Path::new(&dir), &worker_cancel, &sizes).is_none() {
+                    return;
+                }
+            }
+            worker_cancel.store(true, Ordering::Relaxed);
+        });
+
+        self.job = Some(Job {
+            dirs: missing,
+            cancel,
+        });
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some(job) = self.job.take() {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Forgets `path` and everything below it, and takes `removed` bytes off
+    /// every cached ancestor, e.g. after deleting it.
+    pub fn remove(&mut self, path: &str, removed: u64) {
+        self.cancel();
+        if let Ok(mut sizes) = self.sizes.lock() {
+            sizes.retain(|dir, _| !Path::new(dir).starts_with(path));
+            for ancestor in Path::new(path).ancestors().skip(1) {
+                if let Some(size) = sizes.get_mut(&ancestor.display().to_string()) {
+                    *size = size.saturating_sub(removed);
+                }
+            }
+        }
+    }
+
+    /// Forgets the size of `path` and its ancestors' totals, keeping those
+    /// of the directories below it, e.g. after an entry directly in it
+    /// changed on disk.
+    pub fn changed(&mut self, path: &str) {
+        self.cancel();
+        if let Ok(mut sizes) = self.sizes.lock() {
+            for ancestor in Path::new(path).ancestors() {
+                sizes.remove(&ancestor.display().to_string());
+            }
+        }
+    }
+
+    /// Forgets `path`, everything below it and its ancestors so they are
+    /// computed again, e.g. after something was copied into it.
+    pub fn invalidate(&mut self, path: &str) {
+        self.cancel();
+        if let Ok(mut sizes) = self.sizes.lock() {
+            sizes.retain(|dir, _| !Path::new(dir).starts_with(path));
+            for ancestor in Path::new(path).ancestors() {
+                sizes.remove(&ancestor.display().to_string());
+            }
+        }
+    }
+}
+
+/// Sums the apparent size of every file under `dir` without following
+/// symlinks. Returns `None` when cancelled. The scan is cancelled before
+/// sizes are forgotten, and checked under the same lock before a size is
+/// stored, so a stale size never replaces a forgotten one.
+fn scan(dir: &Path, cancel: &AtomicBool, sizes: &SizeMap) -> Option<u64> {
+    if cancel.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let key = dir.display().to_string();
+    if let Some(size) = sizes.lock().ok()?.get(&key) {
+        return Some(*size);
+    }
+
+    let mut total = 0;
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let meta = match entry.metadata() {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            total += if meta.is_dir() {
+                scan(&entry.path(), cancel, sizes)?
+            } else {
+                meta.len()
+            };
+        }
+    }
+
+    let mut sizes = sizes.lock().ok()?;
+    if cancel.load(Ordering::Relaxed) {
+        return None;
+    }
+    sizes.insert(key, total);
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::write(dir.path().join("a/one"), [0; 10]).unwrap();
+        fs::write(dir.path().join("a/b/two"), [0; 20]).unwrap();
+        dir
+    }
+
+    fn key(path: &Path) -> String {
+        path.display().to_string()
+    }
+
+    #[test]
+    fn scan_caches_every_directory_it_passes() {
+        let dir = tree();
+        let sizes = SizeMap::default();
+
+        let total = scan(&dir.path().join("a"), &AtomicBool::new(false), &sizes);
+
+        assert_eq!(total, Some(30));
+        let sizes = sizes.lock().unwrap();
+        assert_eq!(sizes[&key(&dir.path().join("a/b"))], 20);
+    }
+
+    #[test]
+    fn cancelled_scan_stores_nothing() {
+        let dir = tree();
+        let sizes = SizeMap::default();
+
+        let total = scan(&dir.path().join("a"), &AtomicBool::new(true), &sizes);
+
+        assert_eq!(total, None);
+        assert!(sizes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn changed_keeps_the_sizes_below() {
+        let dir = tree();
+        let mut dir_sizes = DirSizes::default();
+        scan(
+            &dir.path().join("a"),
+            &AtomicBool::new(false),
+            &dir_sizes.sizes,
+        );
+
+        dir_sizes.changed(&key(&dir.path().join("a")));
+
+        assert_eq!(dir_sizes.get(&key(&dir.path().join("a"))), None);
+        assert_eq!(dir_sizes.get(&key(&dir.path().join("a/b"))), Some(20));
+    }
+}
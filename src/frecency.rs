@@ -0,0 +1,308 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use tempfile::NamedTempFile;
+
+use crate::config;
+
+const FRECENCY_FILE: &str = "frecency";
+/// Once the ranks add up to more than this, they are all scaled down so old
+/// directories eventually drop out, the same ageing zoxide uses.
+const MAX_TOTAL_RANK: f64 = 10_000.0;
+
+/// Visits are written back in batches of this many, and on exit.
+const SAVE_EVERY: usize = 20;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+#[derive(Clone, Debug)]
+struct Entry {
+    rank: f64,
+    last_access: u64,
+}
+
+impl Entry {
+    fn score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_access);
+        let factor = if age < HOUR {
+            4.0
+        } else if age < DAY {
+            2.0
+        } else if age < WEEK {
+            0.5
+        } else {
+            0.25
+        };
+        self.rank * factor
+    }
+}
+
+/// How often and how recently directories were visited, persisted to the
+/// data dir as `rank|last_access|path` lines.
+#[derive(Clone, Debug, Default)]
+pub struct Frecency {
+    entries: HashMap<String, Entry>,
+    file: Option<PathBuf>,
+    /// Visits not written back yet.
+    unsaved: usize,
+}
+
+impl Frecency {
+    pub fn load() -> Result<Self> {
+        Self::load_from(config::data_dir().map(|dir| dir.join(FRECENCY_FILE)))
+    }
+
+    fn load_from(file: Option<PathBuf>) -> Result<Self> {
+        let mut entries = HashMap::new();
+        if let Some(file) = file.as_ref().filter(|f| f.exists()) {
+            let contents = std::fs::read_to_string(file)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+            for line in contents.lines() {
+                let mut parts = line.splitn(3, '|');
+                let (rank, last_access, path) = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(rank), Some(last_access), Some(path)) => (rank, last_access, path),
+                    _ => continue,
+                };
+                if let (Ok(rank), Ok(last_access)) = (rank.parse(), last_access.parse()) {
+                    entries.insert(path.to_string(), Entry { rank, last_access });
+                }
+            }
+        }
+
+        Ok(Self {
+            entries,
+            file,
+            unsaved: 0,
+        })
+    }
+
+    /// Records a visit to `dir`, writing the database back once enough
+    /// visits have piled up.
+    pub fn visit(&mut self, dir: &str) -> Result<()> {
+        if dir.is_empty() {
+            return Ok(());
+        }
+
+        let now = now();
+        let entry = self.entries.entry(dir.to_string()).or_insert(Entry {
+            rank: 0.0,
+            last_access: now,
+        });
+        entry.rank += 1.0;
+        entry.last_access = now;
+
+        let total: f64 = self.entries.values().map(|e| e.rank).sum();
+        if total > MAX_TOTAL_RANK {
+            let scale = 0.9 * MAX_TOTAL_RANK / total;
+            self.entries.retain(|_, e| {
+                e.rank *= scale;
+                e.rank >= 1.0
+            });
+        }
+
+        self.unsaved += 1;
+        if self.unsaved >= SAVE_EVERY {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Directories matching every whitespace separated term of `query` in
+    /// order (case-insensitively), best score first. The last term has to
+    /// match the final path component, so `pro walk` finds `~/projects/walker`.
+    pub fn matches(&self, query: &str, exclude: &str) -> Vec<String> {
+        let now = now();
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+
+        let mut matches: Vec<(&String, f64)> = self
+            .entries
+            .iter()
+            .filter(|(path, _)| path.as_str() != exclude && matches_terms(path, &terms))
+            .filter(|(path, _)| Path::new(path).is_dir())
+            .map(|(path, entry)| (path, entry.score(now)))
+            .collect();
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        matches.into_iter().map(|(path, _)| path.clone()).collect()
+    }
+
+    /// Writes back the visits recorded since the last save, if any. The
+    /// database is replaced through a temporary file, so a crash midway
+    /// leaves the old one whole, and a failed write is retried next time.
+    pub fn save(&mut self) -> Result<()> {
+        let file = match &self.file {
+            Some(file) if self.unsaved > 0 => file,
+            _ => return Ok(()),
+        };
+
+        let dir = file.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|(path, e)| format!("{}|{}|{}\n", e.rank, e.last_access, path))
+            .collect();
+        let mut temp = NamedTempFile::new_in(dir)
+            .with_context(|| format!("failed to create a file in {}", dir.display()))?;
+        temp.write_all(contents.as_bytes())
+            .and_then(|()| temp.persist(file).map(|_| ()).map_err(|e| e.error))
+            .with_context(|| format!("failed to write {}", file.display()))?;
+
+        self.unsaved = 0;
+        Ok(())
+    }
+}
+
+fn matches_terms(path: &str, terms: &[String]) -> bool {
+    let path = path.to_lowercase();
+    let mut rest = path.as_str();
+    for term in terms {
+        match rest.find(term.as_str()) {
+            Some(idx) => rest = &rest[idx + term.len()..],
+            None => return false,
+        }
+    }
+
+    match terms.last() {
+        Some(last) => Path::new(&path)
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().contains(last.as_str())),
+        None => true,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn frecency(file: Option<PathBuf>, entries: &[(&str, f64, u64)]) -> Frecency {
+        Frecency {
+            entries: entries
+                .iter()
+                .map(|(path, rank, last_access)| {
+                    let entry = Entry {
+                        rank: *rank,
+                        last_access: *last_access,
+                    };
+                    (path.to_string(), entry)
+                })
+                .collect(),
+            file,
+            unsaved: 0,
+        }
+    }
+
+    fn make_dirs(root: &Path, dirs: &[&str]) -> Vec<String> {
+        dirs.iter()
+            .map(|dir| {
+                let path = root.join(dir);
+                fs::create_dir_all(&path).unwrap();
+                path.display().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recent_visits_outrank_old_frequent_ones() {
+        let root = tempfile::tempdir().unwrap();
+        let dirs = make_dirs(root.path(), &["old", "recent", "current"]);
+        let now = now();
+        let frecency = frecency(
+            None,
+            &[
+                (&dirs[0], 10.0, now - 2 * WEEK),
+                (&dirs[1], 2.0, now),
+                (&dirs[2], 50.0, now),
+            ],
+        );
+
+        assert_eq!(
+            frecency.matches("", &dirs[2]),
+            [dirs[1].clone(), dirs[0].clone()]
+        );
+    }
+
+    #[test]
+    fn matches_need_the_terms_in_order_and_the_last_in_the_name() {
+        let root = tempfile::tempdir().unwrap();
+        let dirs = make_dirs(
+            root.path(),
+            &["projects/walker", "walker/projects", "projects/walker/src"],
+        );
+        let now = now();
+        let entries: Vec<_> = dirs.iter().map(|dir| (dir.as_str(), 1.0, now)).collect();
+        let mut frecency = frecency(None, &entries);
+        frecency.entries.insert(
+            root.path().join("gone/walker").display().to_string(),
+            Entry {
+                rank: 1.0,
+                last_access: now,
+            },
+        );
+
+        assert_eq!(frecency.matches("pro walk", ""), [dirs[0].clone()]);
+        assert_eq!(frecency.matches("WALK PRO", ""), [dirs[1].clone()]);
+        assert!(frecency.matches("walk pro walk", "").is_empty());
+    }
+
+    #[test]
+    fn ranks_are_scaled_down_past_the_limit() {
+        let mut frecency = frecency(None, &[("/a", MAX_TOTAL_RANK - 0.5, 0), ("/b", 1.0, 0)]);
+
+        frecency.visit("/a").unwrap();
+
+        let rank = frecency.entries["/a"].rank;
+        assert!((rank - 0.9 * MAX_TOTAL_RANK * 10_000.5 / 10_001.5).abs() < 1e-6);
+        assert!(!frecency.entries.contains_key("/b"));
+    }
+
+    #[test]
+    fn save_writes_back_only_after_visits() {
+        let root = tempfile::tempdir().unwrap();
+        let file = root.path().join("data/frecency");
+        let mut frecency = frecency(Some(file.clone()), &[("/a", 3.0, 7)]);
+
+        frecency.save().unwrap();
+        assert!(!file.exists());
+
+        frecency.visit("/b").unwrap();
+        frecency.save().unwrap();
+        let loaded = Frecency::load_from(Some(file)).unwrap();
+        assert_eq!(loaded.entries.len(), 2);
+        assert_eq!(loaded.entries["/a"].rank, 3.0);
+        assert_eq!(loaded.entries["/a"].last_access, 7);
+    }
+
+    #[test]
+    fn failed_saves_keep_the_visits_for_the_next_one() {
+        let root = tempfile::tempdir().unwrap();
+        let blocker = root.path().join("data");
+        fs::write(&blocker, "").unwrap();
+        let mut frecency = frecency(Some(blocker.join("frecency")), &[]);
+
+        frecency.visit("/a").unwrap();
+        assert!(frecency.save().is_err());
+        assert_eq!(frecency.unsaved, 1);
+
+        fs::remove_file(&blocker).unwrap();
+        frecency.save().unwrap();
+        assert_eq!(frecency.unsaved, 0);
+        assert!(blocker.join("frecency").exists());
+    }
+}
@@ -0,0 +1,586 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::command;
+
+/// The state of a path in its repository, ordered from least to most in
+/// need of attention so a directory can show the worst of its contents.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GitStatus {
+    Ignored,
+    Untracked,
+    Staged,
+    Modified,
+    Conflicted,
+}
+
+impl GitStatus {
+    pub fn symbol(self) -> char {
+        match self {
+            GitStatus::Ignored => '!',
+            GitStatus::Untracked => '?',
+            GitStatus::Staged => '+',
+            GitStatus::Modified => 'M',
+            GitStatus::Conflicted => 'U',
+        }
+    }
+
+    /// The status of a `git status --porcelain` entry from its two letter
+    /// code. Changes in the work tree win over staged ones.
+    fn from_code(code: &str) -> Option<Self> {
+        let mut letters = code.chars();
+        let (index, worktree) = (letters.next()?, letters.next()?);
+        let status = match (index, worktree) {
+            ('!', '!') => GitStatus::Ignored,
+            ('?', '?') => GitStatus::Untracked,
+            ('U', _) | (_, 'U') | ('A', 'A') | ('D', 'D') => GitStatus::Conflicted,
+            (_, ' ') => GitStatus::Staged,
+            _ => GitStatus::Modified,
+        };
+        Some(status)
+    }
+}
+
+/// What `git status` said about a repository.
+#[derive(Debug)]
+pub struct RepoStatus {
+    /// The top of the work tree, named the way the panel reached it.
+    pub root: PathBuf,
+    pub branch: String,
+    entries: HashMap<PathBuf, GitStatus>,
+    /// The worst status below each directory that has changes.
+    dirty_dirs: HashMap<PathBuf, GitStatus>,
+}
+
+impl RepoStatus {
+    /// Runs `git status` for the repository containing `dir`, or returns
+    /// `None` when there is none. Looking for `ignored` files means walking
+    /// directories such as `target`, so it is left out unless asked for.
+    pub fn load(dir: &str, ignored: bool) -> Result<Option<Self>> {
+        let cdup = match git(dir, &["rev-parse", "--show-cdup"]) {
+            Ok(cdup) => cdup,
+            Err(_) => return Ok(None),
+        };
+        let root = command::resolve_path(dir, cdup.trim_end());
+        let mut args = vec!["status", "--porcelain=v1", "-z", "--branch"];
+        if ignored {
+            args.push("--ignored");
+        }
+        let output = git(&root.display().to_string(), &args)?;
+        Ok(Some(Self::parse(root, &output)))
+    }
+
+    /// The directory whose changes, such as staging from another terminal,
+    /// should trigger a reload.
+    pub fn git_dir(&self) -> PathBuf {
+        self.root.join(".git")
+    }
+
+    fn parse(root: PathBuf, output: &str) -> Self {
+        let mut status = Self {
+            root,
+            branch: String::new(),
+            entries: HashMap::new(),
+            dirty_dirs: HashMap::new(),
+        };
+
+        let mut fields = output.split('\0');
+        while let Some(field) = fields.next() {
+            if let Some(header) = field.strip_prefix("## ") {
+                status.branch = branch_name(header);
+                continue;
+            }
+            if field.len() < 4 {
+                continue;
+            }
+
+            let (code, path) = field.split_at(3);
+            if code.starts_with('R') || code.starts_with('C') {
+                // Renames and copies are followed by the original path.
+                fields.next();
+            }
+            if let Some(git_status) = GitStatus::from_code(code) {
+                status.insert(path.trim_end_matches('/'), git_status);
+            }
+        }
+        status
+    }
+
+    fn insert(&mut self, path: &str, git_status: GitStatus) {
+        let path = self.root.join(path);
+        if git_status != GitStatus::Ignored {
+            for dir in path.ancestors().skip(1) {
+                let worst = self
+                    .dirty_dirs
+                    .entry(dir.to_path_buf())
+                    .or_insert(git_status);
+                *worst = (*worst).max(git_status);
+                if dir == self.root {
+                    break;
+                }
+            }
+        }
+        self.entries.insert(path, git_status);
+    }
+
+    /// The status of `path`, which is also that of an untracked or ignored
+    /// directory it is in. Clean paths have none.
+    pub fn status_of(&self, path: &Path) -> Option<GitStatus> {
+        if !path.starts_with(&self.root) {
+            return None;
+        }
+        if let Some(status) = self.entries.get(path) {
+            return Some(*status);
+        }
+        if let Some(status) = self.dirty_dirs.get(path) {
+            return Some(*status);
+        }
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+            .filter_map(|dir| self.entries.get(dir))
+            .find(|status| matches!(status, GitStatus::Untracked | GitStatus::Ignored))
+            .copied()
+    }
+
+    /// Whether `dir` is covered by this repository rather than by one
+    /// nested inside it.
+    fn covers(&self, dir: &Path) -> bool {
+        dir.starts_with(&self.root)
+            && dir
+                .ancestors()
+                .take_while(|ancestor| *ancestor != self.root)
+                .all(|ancestor| !ancestor.join(".git").exists())
+    }
+}
+
+/// The branch from the `## ` header of `git status --branch`.
+fn branch_name(header: &str) -> String {
+    if let Some(branch) = header.strip_prefix("No commits yet on ") {
+        return branch.to_string();
+    }
+    if header.starts_with("HEAD (no branch)") {
+        return "HEAD (detached)".to_string();
+    }
+    header
+        .split("...")
+        .next()
+        .unwrap_or(header)
+        .split(' ')
+        .next()
+        .unwrap_or(header)
+        .to_string()
+}
+
+/// Runs git in `dir` and returns its output. Optional locks are off so a
+/// background `git status` never gets in the way of the user's own git.
+pub fn git<S: AsRef<OsStr>>(dir: &str, args: &[S]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .output()
+        .context("failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {}: {}",
+            args.first().map_or(String::new(), |arg| arg
+                .as_ref()
+                .to_string_lossy()
+                .to_string()),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Stages `paths` (relative to `root`), including deletions.
+pub fn stage(root: &Path, paths: &[PathBuf]) -> Result<()> {
+    run_on_paths(root, &["add", "-A"], paths).map(|_| ())
+}
+
+/// Takes `paths` out of the index, leaving the work tree alone. Before the
+/// first commit that means forgetting them altogether.
+pub fn unstage(root: &Path, paths: &[PathBuf]) -> Result<()> {
+    let has_head = git(
+        &root.display().to_string(),
+        &["rev-parse", "--verify", "-q", "HEAD"],
+    )
+    .is_ok();
+    if has_head {
+        run_on_paths(root, &["restore", "--staged"], paths)
+    } else {
+        run_on_paths(root, &["rm", "-r", "-q", "--cached"], paths)
+    }
+    .map(|_| ())
+}
+
+/// Throws away the unstaged changes to `paths`, restoring them from the
+/// index. Untracked files are left alone.
+pub fn discard(root: &Path, paths: &[PathBuf]) -> Result<()> {
+    run_on_paths(root, &["restore", "--worktree"], paths).map(|_| ())
+}
+
+/// The unstaged and staged changes to `paths`, plus the whole content of
+/// those that are untracked.
+pub fn diff(root: &Path, paths: &[PathBuf], untracked: &[PathBuf]) -> Result<String> {
+    let mut text = String::new();
+    // Without paths git would diff the whole repository.
+    let tracked = if paths.is_empty() {
+        vec![]
+    } else {
+        vec![
+            ("Unstaged", &["diff"][..]),
+            ("Staged", &["diff", "--cached"][..]),
+        ]
+    };
+    for (heading, args) in tracked {
+        let diff = run_on_paths(root, args, paths)?;
+        if !diff.is_empty() {
+            text.push_str(&format!("# {}\n{}\n", heading, diff));
+        }
+    }
+    for path in untracked {
+        // `--no-index` exits with 1 when the files differ, which they do.
+        let output = Command::new("git")
+            .args(["diff", "--no-index", "--", "/dev/null"])
+            .arg(path)
+            .current_dir(root)
+            .output()
+            .context("failed to run git")?;
+        text.push_str(&format!(
+            "# Untracked\n{}\n",
+            String::from_utf8_lossy(&output.stdout)
+        ));
+    }
+    Ok(text)
+}
+
+fn run_on_paths(root: &Path, args: &[&str], paths: &[PathBuf]) -> Result<String> {
+    let mut args: Vec<&OsStr> = args.iter().map(OsStr::new).collect();
+    args.push(OsStr::new("--"));
+    args.extend(paths.iter().map(|path| path.as_os_str()));
+    git(&root.display().to_string(), &args)
+}
+
+type StatusMap = Arc<Mutex<HashMap<String, Option<Arc<RepoStatus>>>>>;
+
+/// A background run of `git status` for a set of directories.
+#[derive(Debug)]
+struct Job {
+    dirs: HashSet<String>,
+    cancel: Arc<AtomicBool>,
+}
+
+/// The repository status of each directory on screen (`None` outside a
+/// repository), computed on a background thread and kept until
+/// `invalidate`d, e.g. when the watcher sees a change.
+#[derive(Debug, Default)]
+pub struct GitStatuses {
+    statuses: StatusMap,
+    job: Option<Job>,
+    ignored: bool,
+}
+
+impl GitStatuses {
+    /// The status of the repository `dir` is in, once known.
+    pub fn get(&self, dir: &str) -> Option<Option<Arc<RepoStatus>>> {
+        let statuses = self.statuses.lock().ok()?;
+        statuses.get(dir).cloned()
+    }
+
+    /// Whether ignored files are looked for, which reloads every status.
+    pub fn set_ignored(&mut self, ignored: bool) {
+        if self.ignored != ignored {
+            self.ignored = ignored;
+            self.cancel();
+            if let Ok(mut statuses) = self.statuses.lock() {
+                statuses.clear();
+            }
+        }
+    }
+
+    /// Makes sure the statuses of `dirs` are known or being computed. A
+    /// directory inside a repository already loaded shares its status.
+    pub fn request(&mut self, dirs: HashSet<String>) {
+        let running = self
+            .job
+            .as_ref()
+            .filter(|job| !job.cancel.load(Ordering::Relaxed));
+        let mut missing = HashSet::new();
+        if let Ok(mut statuses) = self.statuses.lock() {
+            for dir in dirs {
+                if statuses.contains_key(&dir) || running.is_some_and(|job| job.dirs.contains(&dir))
+                {
+                    continue;
+                }
+                let known = statuses
+                    .values()
+                    .flatten()
+                    .find(|repo| repo.covers(Path::new(&dir)))
+                    .cloned();
+                match known {
+                    Some(repo) => {
+                        statuses.insert(dir, Some(repo));
+                    }
+                    None => {
+                        missing.insert(dir);
+                    }
+                }
+            }
+            // The new job takes over what the running one has not done.
+            if let Some(job) = running.filter(|_| !missing.is_empty()) {
+                let unfinished = job.dirs.iter().filter(|dir| !statuses.contains_key(*dir));
+                missing.extend(unfinished.cloned());
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+        self.cancel();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let statuses = Arc::clone(&self.statuses);
+        let worker_cancel = Arc::clone(&cancel);
+        let worker_dirs: Vec<String> = missing.iter().cloned().collect();
+        let ignored = self.ignored;
+        thread::spawn(move || {
+            for dir in worker_dirs {
+                let status = RepoStatus::load(&dir, ignored).ok().flatten().map(Arc::new);
+                // Checked under the lock, which `invalidate` only takes
+                // after cancelling, so a stale status is never stored.
+                let mut statuses = match statuses.lock() {
+                    Ok(statuses) => statuses,
+                    Err(_) => return,
+                };
+                if worker_cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+                statuses.insert(dir, status);
+            }
+            worker_cancel.store(true, Ordering::Relaxed);
+        });
+
+        self.job = Some(Job {
+            dirs: missing,
+            cancel,
+        });
+    }
+
+    fn cancel(&mut self) {
+        if let Some(job) = self.job.take() {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Forgets the status of the repository `path` is in, so it is run
+    /// again, e.g. after something in it changed.
+    pub fn invalidate(&mut self, path: &str) {
+        self.cancel();
+        let path = Path::new(path);
+        if let Ok(mut statuses) = self.statuses.lock() {
+            statuses.retain(|dir, status| match status {
+                Some(repo) => !path.starts_with(&repo.root),
+                None => !Path::new(dir).starts_with(path),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn repo() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        run(dir.path(), &["init", "-q"]);
+        dir
+    }
+
+    fn run(root: &Path, args: &[&str]) -> String {
+        let mut all = vec!["-c", "user.name=test", "-c", "user.email=test@example.com"];
+        all.extend_from_slice(args);
+        git(&root.display().to_string(), &all).unwrap()
+    }
+
+    fn commit(root: &Path) {
+        run(root, &["commit", "-q", "-m", "commit"]);
+    }
+
+    /// The porcelain code of each path, e.g. `"A "` for a staged new file.
+    fn codes(root: &Path) -> HashMap<String, String> {
+        run(root, &["status", "--porcelain=v1", "-z"])
+            .split('\0')
+            .filter(|field| field.len() > 3)
+            .map(|field| (field[3..].to_string(), field[..2].to_string()))
+            .collect()
+    }
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn stage_adds_new_and_deleted_files() {
+        let dir = repo();
+        let root = dir.path();
+        fs::write(root.join("gone"), "gone\n").unwrap();
+        stage(root, &paths(&["gone"])).unwrap();
+        commit(root);
+
+        fs::remove_file(root.join("gone")).unwrap();
+        fs::write(root.join("new"), "new\n").unwrap();
+        stage(root, &paths(&["gone", "new"])).unwrap();
+
+        let codes = codes(root);
+        assert_eq!(codes["gone"], "D ");
+        assert_eq!(codes["new"], "A ");
+    }
+
+    #[test]
+    fn unstage_before_first_commit_forgets_the_file() {
+        let dir = repo();
+        let root = dir.path();
+        fs::write(root.join("file"), "file\n").unwrap();
+        stage(root, &paths(&["file"])).unwrap();
+
+        unstage(root, &paths(&["file"])).unwrap();
+
+        assert_eq!(codes(root)["file"], "??");
+        assert!(root.join("file").exists());
+    }
+
+    #[test]
+    fn unstage_after_commit_keeps_the_work_tree() {
+        let dir = repo();
+        let root = dir.path();
+        fs::write(root.join("file"), "one\n").unwrap();
+        stage(root, &paths(&["file"])).unwrap();
+        commit(root);
+        fs::write(root.join("file"), "two\n").unwrap();
+        stage(root, &paths(&["file"])).unwrap();
+
+        unstage(root, &paths(&["file"])).unwrap();
+
+        assert_eq!(codes(root)["file"], " M");
+        assert_eq!(fs::read_to_string(root.join("file")).unwrap(), "two\n");
+    }
+
+    #[test]
+    fn discard_keeps_staged_content_and_untracked_files() {
+        let dir = repo();
+        let root = dir.path();
+        fs::write(root.join("file"), "one\n").unwrap();
+        stage(root, &paths(&["file"])).unwrap();
+        commit(root);
+        fs::write(root.join("file"), "staged\n").unwrap();
+        stage(root, &paths(&["file"])).unwrap();
+        fs::write(root.join("file"), "unstaged\n").unwrap();
+        fs::write(root.join("untracked"), "untracked\n").unwrap();
+
+        discard(root, &paths(&["file"])).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("file")).unwrap(), "staged\n");
+        assert_eq!(codes(root)["file"], "M ");
+        assert_eq!(
+            fs::read_to_string(root.join("untracked")).unwrap(),
+            "untracked\n"
+        );
+    }
+
+    #[test]
+    fn diff_shows_untracked_files_whole() {
+        let dir = repo();
+        let root = dir.path();
+        fs::write(root.join("untracked"), "first\nsecond\n").unwrap();
+
+        let text = diff(root, &[], &paths(&["untracked"])).unwrap();
+
+        assert!(text.starts_with("# Untracked\n"));
+        assert!(text.contains("+first\n+second\n"));
+        assert!(!text.contains("# Unstaged"));
+    }
+
+    #[test]
+    fn diff_separates_unstaged_and_staged_changes() {
+        let dir = repo();
+        let root = dir.path();
+        fs::write(root.join("file"), "one\n").unwrap();
+        stage(root, &paths(&["file"])).unwrap();
+        commit(root);
+        fs::write(root.join("file"), "two\n").unwrap();
+        stage(root, &paths(&["file"])).unwrap();
+        fs::write(root.join("file"), "three\n").unwrap();
+
+        let text = diff(root, &paths(&["file"]), &[]).unwrap();
+
+        let staged = text.find("# Staged").unwrap();
+        assert!(text.starts_with("# Unstaged"));
+        assert!(text[..staged].contains("-two\n+three\n"));
+        assert!(text[staged..].contains("-one\n+two\n"));
+    }
+
+    #[test]
+    fn parse_reads_branch_renames_and_dirty_dirs() {
+        let root = PathBuf::from("/repo");
+        let output = [
+            "## main...origin/main [ahead 1]",
+            "R  src/new.rs",
+            "src/old.rs",
+            " M README.md",
+            "?? notes/",
+            "!! target/",
+            "UU src/conflict.rs",
+            "",
+        ]
+        .join("\0");
+
+        let status = RepoStatus::parse(root.clone(), &output);
+
+        assert_eq!(status.branch, "main");
+        assert_eq!(
+            status.status_of(&root.join("src/new.rs")),
+            Some(GitStatus::Staged)
+        );
+        assert_eq!(status.status_of(&root.join("src/old.rs")), None);
+        assert_eq!(
+            status.status_of(&root.join("README.md")),
+            Some(GitStatus::Modified)
+        );
+        assert_eq!(
+            status.status_of(&root.join("notes/todo.txt")),
+            Some(GitStatus::Untracked)
+        );
+        assert_eq!(
+            status.status_of(&root.join("target/debug")),
+            Some(GitStatus::Ignored)
+        );
+        assert_eq!(
+            status.status_of(&root.join("src")),
+            Some(GitStatus::Conflicted)
+        );
+        assert_eq!(status.status_of(Path::new("/elsewhere")), None);
+    }
+
+    #[test]
+    fn branch_name_handles_unborn_and_detached_heads() {
+        assert_eq!(branch_name("No commits yet on main"), "main");
+        assert_eq!(branch_name("HEAD (no branch)"), "HEAD (detached)");
+        assert_eq!(branch_name("topic"), "topic");
+    }
+}
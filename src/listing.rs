@@ -0,0 +1,216 @@
+use std::{cmp::Ordering, fmt, path::Path, str::FromStr};
+
+use anyhow::{bail, Result};
+
+use crate::app::{get_contents, Item};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SortKind {
+    Name,
+    Size,
+    Modified,
+    Extension,
+}
+
+impl SortKind {
+    pub fn next(self) -> Self {
+        match self {
+            SortKind::Name => SortKind::Size,
+            SortKind::Size => SortKind::Modified,
+            SortKind::Modified => SortKind::Extension,
+            SortKind::Extension => SortKind::Name,
+        }
+    }
+}
+
+impl FromStr for SortKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(SortKind::Name),
+            "size" => Ok(SortKind::Size),
+            "modified" | "mtime" | "time" => Ok(SortKind::Modified),
+            "extension" | "ext" => Ok(SortKind::Extension),
+            _ => bail!(
+                "unknown sort '{}' (expected name, size, modified or extension)",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SortKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SortKind::Name => "name",
+            SortKind::Size => "size",
+            SortKind::Modified => "modified",
+            SortKind::Extension => "extension",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How a directory listing is sorted and filtered, shared by the panels and
+/// `walker ls` so both show the same entries in the same order.
+#[derive(Clone, Debug)]
+pub struct ListOptions {
+    pub sort: SortKind,
+    pub reverse: bool,
+    pub dirs_first: bool,
+    pub show_hidden: bool,
+    pub filter: Option<String>,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            sort: SortKind::Name,
+            reverse: false,
+            dirs_first: false,
+            show_hidden: true,
+            filter: None,
+        }
+    }
+}
+
+impl ListOptions {
+    pub fn describe(&self) -> String {
+        let mut description = format!("sorted by {}", self.sort);
+        if self.reverse {
+            description.push_str(" (reversed)");
+        }
+        if self.dirs_first {
+            description.push_str(", directories first");
+        }
+        if !self.show_hidden {
+            description.push_str(", hidden files hidden");
+        }
+        if let Some(filter) = &self.filter {
+            description.push_str(&format!(", filtered by '{}'", filter));
+        }
+        description
+    }
+}
+
+/// The entries of `path` as `options` wants them shown.
+pub fn list_dir(path: &str, options: &ListOptions) -> Result<Vec<Item>> {
+    let mut items: Vec<Item> = get_contents(path)?
+        .into_iter()
+        .filter(|item| options.show_hidden || !file_name(item).starts_with('.'))
+        .filter(|item| {
+            options
+                .filter
+                .as_deref()
+                .is_none_or(|pattern| matches_filter(pattern, file_name(item)))
+        })
+        .collect();
+
+    sort_items(&mut items, options);
+    Ok(items)
+}
+
+pub fn sort_items(items: &mut [Item], options: &ListOptions) {
+    items.sort_by(|a, b| {
+        let dirs = if options.dirs_first {
+            b.is_dir.cmp(&a.is_dir)
+        } else {
+            Ordering::Equal
+        };
+        let ordering = match options.sort {
+            SortKind::Name => Ordering::Equal,
+            SortKind::Size => a.size.cmp(&b.size),
+            SortKind::Modified => a.modified_date.cmp(&b.modified_date),
+            SortKind::Extension => extension(a).cmp(extension(b)),
+        }
+        .then_with(|| file_name(a).cmp(file_name(b)));
+
+        dirs.then(if options.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        })
+    });
+}
+
+/// A glob (`*` and `?`) when the pattern has wildcards, otherwise a
+/// substring; both ignore case.
+pub fn matches_filter(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = name.to_lowercase();
+    if pattern.contains(&['*', '?'][..]) {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        glob_match(&pattern, &name)
+    } else {
+        name.contains(&pattern)
+    }
+}
+
+/// Matches with a single backtrack point, the last `*` seen, so patterns
+/// like `*a*a*a*b` take at most pattern length times name length steps
+/// rather than exponentially many.
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and the name position it currently covers up to.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+pub fn file_name(item: &Item) -> &str {
+    Path::new(&item.name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&item.name)
+}
+
+fn extension(item: &Item) -> &str {
+    Path::new(file_name(item))
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_globs_and_substrings_ignore_case() {
+        assert!(matches_filter("*.RS", "main.rs"));
+        assert!(matches_filter("m?in.*", "main.rs"));
+        assert!(matches_filter("ai", "Main.rs"));
+        assert!(!matches_filter("*.rs", "main.rs.bak"));
+        assert!(matches_filter("*", ""));
+        assert!(!matches_filter("?", ""));
+        assert!(matches_filter("a*b*c", "aXbYbZc"));
+        assert!(!matches_filter("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn glob_with_many_stars_is_fast() {
+        let name = "a".repeat(200);
+        assert!(!matches_filter("*a*a*a*a*a*a*a*a*b", &name));
+    }
+}
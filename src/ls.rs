@@ -0,0 +1,180 @@
+use std::{io::Write, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use humansize::{file_size_opts as options, FileSize};
+use serde::Serialize;
+
+use crate::app::{Item, ItemKind};
+use crate::listing::{self, ListOptions, SortKind};
+
+#[derive(Copy, Clone, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => bail!("unknown format '{}' (expected text, json or csv)", s),
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct LsArgs {
+    /// Directory to list
+    #[clap(value_name = "PATH", default_value = ".")]
+    path: PathBuf,
+
+    /// Output format: text, json or csv
+    #[clap(long, default_value = "text")]
+    format: OutputFormat,
+
+    /// Sort by name, size, modified or extension
+    #[clap(long, default_value = "name")]
+    sort: SortKind,
+
+    /// Reverse the sort order
+    #[clap(short, long)]
+    reverse: bool,
+
+    /// List directories before files
+    #[clap(long)]
+    dirs_first: bool,
+
+    /// Leave out entries starting with `.`
+    #[clap(long)]
+    no_hidden: bool,
+
+    /// Only list names containing this text, or matching it as a glob
+    #[clap(long)]
+    filter: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    path: &'a str,
+    kind: &'static str,
+    size: u64,
+    mode: String,
+    perms: &'a str,
+    modified: String,
+    target: Option<String>,
+}
+
+impl<'a> Entry<'a> {
+    fn new(item: &'a Item) -> Self {
+        Self {
+            name: listing::file_name(item),
+            path: &item.name,
+            kind: kind_name(item.kind),
+            size: item.size,
+            mode: format!("{:o}", item.mode & 0o7777),
+            perms: &item.perms,
+            modified: item.modified_date.to_rfc3339(),
+            target: item
+                .link_target
+                .as_ref()
+                .map(|target| target.display().to_string()),
+        }
+    }
+}
+
+pub fn run(args: LsArgs) -> Result<()> {
+    if !args.path.is_dir() {
+        bail!("{}: not a directory", args.path.display());
+    }
+
+    let options = ListOptions {
+        sort: args.sort,
+        reverse: args.reverse,
+        dirs_first: args.dirs_first,
+        show_hidden: !args.no_hidden,
+        filter: args.filter,
+    };
+    let dir = args.path.display().to_string();
+    let items =
+        listing::list_dir(&dir, &options).with_context(|| format!("failed to list {}", dir))?;
+    let entries: Vec<Entry> = items.iter().map(Entry::new).collect();
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    match args.format {
+        OutputFormat::Text => write_text(&mut out, &items)?,
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &entries)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => write_csv(&mut out, &entries)?,
+    }
+    Ok(())
+}
+
+/// The columns of the panels: permissions, size and name, plus the
+/// modification date. Symlinks show their target like `ls -l`.
+fn write_text(out: &mut impl Write, items: &[Item]) -> Result<()> {
+    for item in items {
+        write!(
+            out,
+            "{} {:>9} {} {}",
+            item.perms,
+            item.size.file_size(options::DECIMAL).unwrap_or_default(),
+            item.modified_date.format("%Y-%m-%d %H:%M"),
+            listing::file_name(item),
+        )?;
+        if let Some(target) = &item.link_target {
+            write!(out, " -> {}", target.display())?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_csv(out: &mut impl Write, entries: &[Entry]) -> Result<()> {
+    writeln!(out, "name,path,kind,size,mode,perms,modified,target")?;
+    for e in entries {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            csv_field(e.name),
+            csv_field(e.path),
+            e.kind,
+            e.size,
+            e.mode,
+            csv_field(e.perms),
+            e.modified,
+            csv_field(e.target.as_deref().unwrap_or_default()),
+        )?;
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn kind_name(kind: ItemKind) -> &'static str {
+    match kind {
+        ItemKind::File => "file",
+        ItemKind::Directory => "directory",
+        ItemKind::Symlink => "symlink",
+        ItemKind::BrokenSymlink => "broken_symlink",
+        ItemKind::Socket => "socket",
+        ItemKind::Fifo => "fifo",
+        ItemKind::BlockDevice => "block_device",
+        ItemKind::CharDevice => "char_device",
+    }
+}
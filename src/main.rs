@@ -1,17 +1,18 @@
+use chrono::{DateTime, Local};
 use humansize::{file_size_opts as options, FileSize};
 use std::{
-    default,
-    io::{self, SeekFrom},
-    os::unix::prelude::{CommandExt, PermissionsExt},
+    collections::HashSet,
+    io::{self, BufRead, Write},
     panic,
-    path::Path,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 
-use chrono::{DateTime, Local, TimeZone, Utc};
-use tokio::sync::mpsc::{Receiver, Sender};
-
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::{Parser, Subcommand};
 use crossterm::{
     event::{self, Event as CEvent, KeyCode, KeyEvent},
@@ -21,31 +22,82 @@ use crossterm::{
 
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Span, Spans},
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState, Tabs, Wrap},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState, Tabs, Wrap},
     Frame, Terminal,
 };
-use walkdir::WalkDir;
 
-use crate::app::{App, EditingKind, InputMode};
+use crate::app::{App, CommandOutput, EditingKind, InputMode, ItemKind, PanelKind, RenamePreview};
+use crate::bookmarks::Bookmarks;
+use crate::compare::{DirComparison, FileDiff, RowKind, SyncDirection};
+use crate::config::Config;
+use crate::frecency::Frecency;
+use crate::perms::ChmodDialog;
+use crate::shell::Capture;
+use crate::theme::Theme;
+use crate::view::WalkerView;
+use crate::watch::Watcher;
 use tui_input::backend::crossterm as input_backend;
-use tui_input::{Input, InputResponse, StateChanged};
-use unicode_width::UnicodeWidthStr;
+use tui_input::InputResponse;
 
 mod app;
+mod bookmarks;
+mod command;
+mod compare;
+mod config;
+mod du;
+mod frecency;
+mod git;
+mod listing;
+mod ls;
+mod ops;
+mod pattern;
+mod perms;
+mod rename;
+mod shell;
+mod theme;
 mod view;
+mod watch;
 
 #[derive(Parser, Debug)]
 #[clap(version = "1.0", author = "Jonathan Rothberg")]
 struct Args {
+    /// Directory to open, or a file to open its directory with it selected
+    #[clap(value_name = "PATH")]
+    path: Option<PathBuf>,
+
+    /// Colour theme, either built-in or defined in the config file
+    #[clap(long)]
+    theme: Option<String>,
+
+    /// Start with two permanent panels, like Midnight Commander
+    #[clap(long)]
+    dual_pane: bool,
+
+    /// Show directories with symlinks resolved, like `pwd -P`
+    #[clap(long)]
+    physical: bool,
+
+    /// On exit, write the last directory to FILE (for cd-on-exit)
+    #[clap(long, value_name = "FILE")]
+    choosedir: Option<PathBuf>,
+
+    /// On exit, write the marked or selected paths to FILE, one per line.
+    /// Opening a file picks it and exits.
+    #[clap(long, value_name = "FILE")]
+    choosefiles: Option<PathBuf>,
+
     #[clap(subcommand)]
     subcmd: Option<SubCommand>,
 }
 
 #[derive(Subcommand, Debug)]
-enum SubCommand {}
+enum SubCommand {
+    /// Print a directory listing using the same sorting and filters as the panels
+    Ls(ls::LsArgs),
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -53,20 +105,89 @@ async fn main() -> Result<()> {
 
     match run(_args).await {
         Ok(_) => {}
-        Err(e) => println!("{:?}", e),
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
     }
 
     Ok(())
 }
 
 async fn run(_args: Args) -> Result<()> {
-    match _args {
-        _ => run_ui().await?,
+    if let Some(SubCommand::Ls(ls_args)) = _args.subcmd {
+        return ls::run(ls_args);
+    }
+
+    let start = start_path(_args.path.as_deref())?;
+    let config = Config::load()?;
+    let theme = Theme::resolve(&config, _args.theme.as_deref())?;
+
+    let mut app = App::new();
+    app.set_themes(theme, config.clone());
+    app.set_dual_pane(_args.dual_pane || config.dual_pane.unwrap_or(false));
+    app.set_physical_paths(_args.physical || config.physical_paths.unwrap_or(false));
+    app.set_git_ignored(config.git_ignored.unwrap_or(false));
+    app.set_bookmarks(Bookmarks::load()?);
+    app.set_frecency(Frecency::load()?);
+    app.set_choosing_files(_args.choosefiles.is_some());
+    match Watcher::new() {
+        Ok(watcher) => app.set_watcher(watcher),
+        Err(e) => app.set_status(format!("{:#}", e)),
+    }
+    if start.is_dir() {
+        app.set_current_dir(&start.display().to_string());
+    } else {
+        let parent = start.parent().unwrap_or(&start);
+        app.set_current_dir(&parent.display().to_string());
+        app.select_path(&start.display().to_string());
+    }
+    app.set_dual_pane(app.is_dual_pane());
+
+    let mut app = run_ui(app).await?;
+    app.save_frecency()?;
+
+    if let Some(path) = &_args.choosedir {
+        std::fs::write(path, app.current_dir())?;
+    }
+    if let Some(path) = &_args.choosefiles {
+        let mut chosen = app.chosen_paths().join("\n");
+        if !chosen.is_empty() {
+            chosen.push('\n');
+        }
+        std::fs::write(path, chosen)?;
     }
     Ok(())
 }
 
-async fn run_ui() -> Result<()> {
+/// The absolute path walker starts at: `path` if given, the working
+/// directory otherwise. Symlinks are kept, so the panels show the path as
+/// the shell had it.
+fn start_path(path: Option<&Path>) -> Result<PathBuf> {
+    let cwd = logical_current_dir()?.display().to_string();
+    let path = match path {
+        Some(path) => command::resolve_path(&cwd, &path.display().to_string()),
+        None => PathBuf::from(cwd),
+    };
+
+    if std::fs::symlink_metadata(&path).is_err() {
+        bail!("{}: no such file or directory", path.display());
+    }
+    Ok(path)
+}
+
+/// The working directory as the shell names it in `$PWD`, which may go
+/// through symlinks, falling back to the resolved one.
+fn logical_current_dir() -> Result<PathBuf> {
+    let physical = std::env::current_dir()?;
+    let logical = std::env::var_os("PWD")
+        .map(PathBuf::from)
+        .filter(|pwd| pwd.is_absolute())
+        .filter(|pwd| pwd.canonicalize().ok().as_ref() == Some(&physical));
+    Ok(logical.unwrap_or(physical))
+}
+
+async fn run_ui(mut app: App) -> Result<App> {
     enable_raw_mode()?;
 
     panic::set_hook(Box::new(|info| {
@@ -74,67 +195,197 @@ async fn run_ui() -> Result<()> {
         disable_raw_mode().expect("restore terminal raw mode");
     }));
 
-    let mut rx = start_key_events();
+    let paused = Arc::new(AtomicBool::new(false));
+    let mut rx = start_key_events(Arc::clone(&paused));
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     io::stdout().execute(EnterAlternateScreen)?;
     terminal.clear()?;
 
-    let (mut ui_tx, mut ui_rx): (Sender<Event<KeyEvent>>, Receiver<Event<KeyEvent>>) =
-        tokio::sync::mpsc::channel(1);
-
-    let mut app = App::new();
-    let current_dir = std::env::current_dir()?;
-    app.set_current_dir(&current_dir.display().to_string());
-    let mut table_state = TableState::default();
-    table_state.select(Some(0));
-    app.set_directory_table_state(table_state);
-
     loop {
+        app.reload_changed_dirs();
+        app.update_dir_sizes();
+        app.update_git_statuses();
+        app.update_running_command();
         terminal.draw(|rect| {
             let _ = draw(rect, &mut app);
-        });
+        })?;
 
         tokio::select! {
             Some(event) = rx.recv() =>{
                 match event {
-                    Event::Input(event) =>
+                    Event::Input(event) => {
+                        app.clear_status();
                         match app.input_mode() {
+                            InputMode::Normal if app.pending_key().is_some() => {
+                                match event.code {
+                                    KeyCode::Char(c) => app.complete_key_sequence(c),
+                                    _ => app.cancel_key_sequence(),
+                                }
+                            }
+                            InputMode::Normal if app.chmod_dialog().is_some() => {
+                                match event.code {
+                                    KeyCode::Enter => app.apply_chmod(),
+                                    KeyCode::Esc | KeyCode::Char('q') => app.close_chmod(),
+                                    code => {
+                                        if let Some(dialog) = app.chmod_dialog_mut() {
+                                            handle_chmod_key(dialog, code);
+                                        }
+                                    }
+                                }
+                            }
+                            InputMode::Normal if app.file_diff().is_some() => {
+                                match event.code {
+                                    KeyCode::Esc | KeyCode::Char('q') => app.close_file_diff(),
+                                    KeyCode::Down | KeyCode::Char('j') => app.scroll_file_diff(true),
+                                    KeyCode::Up | KeyCode::Char('k') => app.scroll_file_diff(false),
+                                    KeyCode::Char('v') => app.toggle_diff_layout(),
+                                    _ => {}
+                                }
+                            }
+                            InputMode::Normal if app.dir_comparison().is_some_and(|c| c.pending_sync.is_some()) => {
+                                app.answer_sync(matches!(event.code, KeyCode::Char('y') | KeyCode::Char('Y')));
+                            }
+                            InputMode::Normal if app.dir_comparison().is_some() => {
+                                match event.code {
+                                    KeyCode::Esc | KeyCode::Char('q') => app.close_dir_comparison(),
+                                    KeyCode::Down | KeyCode::Char('j') => {
+                                        if let Some(comparison) = app.dir_comparison_mut() {
+                                            comparison.move_selection(true);
+                                        }
+                                    }
+                                    KeyCode::Up | KeyCode::Char('k') => {
+                                        if let Some(comparison) = app.dir_comparison_mut() {
+                                            comparison.move_selection(false);
+                                        }
+                                    }
+                                    KeyCode::Char('c') => app.toggle_comparison_by_content(),
+                                    KeyCode::Char('s') => app.start_sync(SyncDirection::Both),
+                                    KeyCode::Char('>') => app.start_sync(SyncDirection::ToRight),
+                                    KeyCode::Char('<') => app.start_sync(SyncDirection::ToLeft),
+                                    _ => {}
+                                }
+                            }
+                            InputMode::Normal if app.rename_preview().is_some() => {
+                                match event.code {
+                                    KeyCode::Enter | KeyCode::Char('y') => app.confirm_renames(),
+                                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') => app.cancel_renames(),
+                                    KeyCode::Down | KeyCode::Char('j') => app.move_rename_preview_selection(true),
+                                    KeyCode::Up | KeyCode::Char('k') => app.move_rename_preview_selection(false),
+                                    _ => {}
+                                }
+                            }
+                            InputMode::Normal if app.running_command().is_some() => {
+                                if let KeyCode::Esc | KeyCode::Char('q') = event.code {
+                                    app.kill_running_command();
+                                }
+                            }
+                            InputMode::Normal if app.command_output().is_some() => {
+                                match event.code {
+                                    KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => app.close_command_output(),
+                                    KeyCode::Down | KeyCode::Char('j') => app.scroll_command_output(true),
+                                    KeyCode::Up | KeyCode::Char('k') => app.scroll_command_output(false),
+                                    _ => {}
+                                }
+                            }
+                            InputMode::Normal if app.is_bookmark_manager_open() => {
+                                match event.code {
+                                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('B') => app.close_bookmark_manager(),
+                                    KeyCode::Down | KeyCode::Char('j') => app.move_bookmark_selection(true),
+                                    KeyCode::Up | KeyCode::Char('k') => app.move_bookmark_selection(false),
+                                    KeyCode::Enter | KeyCode::Char('l') => app.jump_to_selected_bookmark(),
+                                    KeyCode::Char('r') => app.start_rename_bookmark(),
+                                    KeyCode::Char('x') => app.delete_selected_bookmark(),
+                                    _ => {}
+                                }
+                            }
                             InputMode::Normal => {
                                 match event.code {
-                                    KeyCode::Char('q') => {
-                                        disable_raw_mode()?;
-                                        io::stdout().execute(LeaveAlternateScreen)?;
-                                        terminal.show_cursor()?;
-                                            break;
-                                    },
+                                    KeyCode::Char('q') => app.quit(),
                                     KeyCode::Down | KeyCode::Char('j') => app.move_selection_down(),
                                     KeyCode::Up | KeyCode::Char('k') => app.move_selection_up(),
-                                    KeyCode::Right | KeyCode::Char('l') => app.move_into_child_dir(),
+                                    KeyCode::Right | KeyCode::Enter | KeyCode::Char('l') => app.open_selected(),
+                                    KeyCode::Char(' ') => app.toggle_mark(),
+                                    KeyCode::Char('U') => app.clear_marks(),
+                                    KeyCode::Char('s') => app.cycle_sort(),
+                                    KeyCode::Char('S') => app.toggle_sort_reverse(),
+                                    KeyCode::Char('.') => app.toggle_hidden(),
+                                    KeyCode::Char('f') => app.start_filter(),
                                     KeyCode::Left | KeyCode::Char('h') => app.move_upto_parent_dir(),
+                                    KeyCode::Char('H') => app.go_back(),
+                                    KeyCode::Char('L') => app.go_forward(),
                                     KeyCode::Char('r') => app.start_rename_file(),
+                                    KeyCode::Char('E') => app.start_bulk_rename(),
+                                    KeyCode::Char('C') => app.start_pattern_rename(),
+                                    KeyCode::Char('p') => app.open_chmod(),
+                                    KeyCode::Char('O') => app.start_chown(),
                                     KeyCode::Char('y') => app.initiate_file_copy(),
+                                    KeyCode::Char('d') => app.initiate_file_move(),
+                                    KeyCode::Tab => app.switch_panel(),
+                                    KeyCode::Char('D') => app.toggle_dual_pane(),
+                                    KeyCode::Char('m') => app.start_key_sequence('m'),
+                                    KeyCode::Char('\'') => app.start_key_sequence('\''),
+                                    KeyCode::Char('Y') => app.start_key_sequence('Y'),
+                                    KeyCode::Char('g') => app.start_key_sequence('g'),
+                                    KeyCode::Char('F') => app.follow_link(),
+                                    KeyCode::Char('=') => app.compare_panels(),
+                                    KeyCode::Char('B') => app.open_bookmark_manager(),
+                                    KeyCode::Char('z') => app.start_jump(),
                                     KeyCode::Char('x') => app.delete_file(),
+                                    KeyCode::Char('u') => app.toggle_disk_usage(),
+                                    KeyCode::Char('R') => app.refresh(),
+                                    KeyCode::Char(':') => app.start_command(),
+                                    KeyCode::Char('!') => app.start_shell_command(false),
+                                    KeyCode::Char('@') => app.start_shell_command(true),
+                                    KeyCode::Char('n') => app.start_new_file(),
+                                    KeyCode::Char('N') => app.start_new_directory(),
+                                    KeyCode::Char('T') => app.cycle_theme(),
+                                    KeyCode::Char('t') => app.open_tab(),
+                                    KeyCode::Char('w') => app.close_tab(),
+                                    KeyCode::Char(']') => app.next_tab(),
+                                    KeyCode::Char('[') => app.previous_tab(),
+                                    KeyCode::Char(c @ '1'..='9') => app.select_tab(c as usize - '1' as usize),
                                     _ => {}
                                 }
                             }
                             InputMode::Editing(ref _kind) => {
                                 match event.code {
                                     KeyCode::Esc => app.set_input_mode(InputMode::Normal),
-                                    KeyCode::Down | KeyCode::Char('j') => app.move_selection_down(),
-                                    KeyCode::Up | KeyCode::Char('k') => app.move_selection_up(),
-                                    KeyCode::Right | KeyCode::Char('l') => app.move_into_child_dir(),
-                                    KeyCode::Left | KeyCode::Char('h') => app.move_upto_parent_dir(),
+                                    KeyCode::Down | KeyCode::Char('j') if app.is_browsing_destination() => app.move_selection_down(),
+                                    KeyCode::Up | KeyCode::Char('k') if app.is_browsing_destination() => app.move_selection_up(),
+                                    KeyCode::Right | KeyCode::Char('l') if app.is_browsing_destination() => app.move_into_child_dir(),
+                                    KeyCode::Left | KeyCode::Char('h') if app.is_browsing_destination() => app.move_upto_parent_dir(),
+                                    KeyCode::Down | KeyCode::Tab if *_kind == EditingKind::Jump => app.move_jump_selection(true),
+                                    KeyCode::Up | KeyCode::BackTab if *_kind == EditingKind::Jump => app.move_jump_selection(false),
+                                    KeyCode::Tab if *_kind == EditingKind::Command => app.complete_command(),
+                                    KeyCode::Tab if *_kind == EditingKind::Chown => app.complete_owner(),
                                     _ => {
                                         let resp = input_backend::to_input_request(CEvent::Key(event))
                                         .and_then(|req| app.text_input_mut().handle(req));
 
                                         match resp {
+                                            Some(InputResponse::StateChanged(_)) if *_kind == EditingKind::Jump => {
+                                                app.update_jump_candidates();
+                                            }
+                                            Some(InputResponse::StateChanged(_)) if *_kind == EditingKind::PatternRename => {
+                                                app.update_pattern_preview();
+                                            }
                                             Some(InputResponse::StateChanged(_)) => {}
                                             Some(InputResponse::Submitted) => {
-                                                if let EditingKind::Rename = _kind {
-                                                    app.rename_file();
+                                                match _kind {
+                                                    EditingKind::Rename => app.rename_file(),
+                                                    EditingKind::Copy | EditingKind::Move => app.complete_transfer(),
+                                                    EditingKind::RenameBookmark => app.rename_bookmark(),
+                                                    EditingKind::Jump => app.complete_jump(),
+                                                    EditingKind::Filter => app.apply_filter(),
+                                                    EditingKind::ConfirmDelete => app.confirm_delete(),
+                                                    EditingKind::ConfirmDiscard => app.confirm_git_discard(),
+                                                    EditingKind::Command => app.run_command(),
+                                                    EditingKind::Shell | EditingKind::ShellCapture => app.run_shell_command(),
+                                                    EditingKind::NewFile | EditingKind::NewDirectory => app.create_from_input(),
+                                                    EditingKind::PatternRename => app.apply_pattern_rename(),
+                                                    EditingKind::Chown => app.apply_chown(),
                                                 }
                                             }
 
@@ -147,13 +398,54 @@ async fn run_ui() -> Result<()> {
                                 }
                             }
                         }
-                    Event::Tick => {}
+                    }
+                    Event::Tick => app.tick(),
                 }
             }
         }
+
+        if let Some((command, dir)) = app.take_foreground_command() {
+            paused.store(true, Ordering::Relaxed);
+            tokio::time::delay_for(TICK_RATE).await;
+            disable_raw_mode()?;
+            io::stdout().execute(LeaveAlternateScreen)?;
+            terminal.show_cursor()?;
+
+            let result = shell::run_foreground(&command, &dir);
+            print!("\nPress Enter to return to walker");
+            io::stdout().flush()?;
+            io::stdin().lock().read_line(&mut String::new())?;
+
+            io::stdout().execute(EnterAlternateScreen)?;
+            enable_raw_mode()?;
+            terminal.clear()?;
+            paused.store(false, Ordering::Relaxed);
+            app.finish_foreground_command(result);
+        }
+
+        if app.should_quit() {
+            disable_raw_mode()?;
+            io::stdout().execute(LeaveAlternateScreen)?;
+            terminal.show_cursor()?;
+            break;
+        }
     }
 
-    Ok(())
+    Ok(app)
+}
+
+fn handle_chmod_key(dialog: &mut ChmodDialog, code: KeyCode) {
+    match code {
+        KeyCode::Down | KeyCode::Char('j') => dialog.move_cursor(1, 0),
+        KeyCode::Up | KeyCode::Char('k') => dialog.move_cursor(-1, 0),
+        KeyCode::Right | KeyCode::Char('l') => dialog.move_cursor(0, 1),
+        KeyCode::Left | KeyCode::Char('h') => dialog.move_cursor(0, -1),
+        KeyCode::Char(' ') => dialog.toggle(),
+        KeyCode::Char('r') => dialog.recursive = !dialog.recursive,
+        KeyCode::Char(c @ '0'..='7') => dialog.push_digit(c),
+        KeyCode::Backspace => dialog.pop_digit(),
+        _ => {}
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -162,12 +454,22 @@ pub enum Event<I> {
     Tick,
 }
 
-fn start_key_events() -> tokio::sync::mpsc::Receiver<Event<KeyEvent>> {
-    let (mut tx, mut rx) = tokio::sync::mpsc::channel(1);
-    let tick_rate = Duration::from_millis(200);
+const TICK_RATE: Duration = Duration::from_millis(200);
+
+/// Reads key events and ticks on a background task. Reading stops while
+/// `paused` is set, so a foreground command gets the terminal's input.
+fn start_key_events(paused: Arc<AtomicBool>) -> tokio::sync::mpsc::Receiver<Event<KeyEvent>> {
+    let (mut tx, rx) = tokio::sync::mpsc::channel(1);
+    let tick_rate = TICK_RATE;
     tokio::spawn(async move {
         let mut last_tick = Instant::now();
         loop {
+            if paused.load(Ordering::Relaxed) {
+                tokio::time::delay_for(Duration::from_millis(50)).await;
+                last_tick = Instant::now();
+                continue;
+            }
+
             let timeout = tick_rate
                 .checked_sub(last_tick.elapsed())
                 .unwrap_or_else(|| Duration::from_secs(0));
@@ -178,10 +480,8 @@ fn start_key_events() -> tokio::sync::mpsc::Receiver<Event<KeyEvent>> {
                 }
             }
 
-            if last_tick.elapsed() >= tick_rate {
-                if let Ok(_) = tx.send(Event::Tick).await {
-                    last_tick = Instant::now();
-                }
+            if last_tick.elapsed() >= tick_rate && tx.send(Event::Tick).await.is_ok() {
+                last_tick = Instant::now();
             }
         }
     });
@@ -200,37 +500,39 @@ fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) -> Result<()> {
             .as_ref(),
         )
         .split(f.size());
-    let titles = vec![app.main_panel_mut().current_dir().as_str()]
+    let theme = app.theme().clone();
+    let titles = app
+        .tabs()
         .iter()
-        .map(|t| {
+        .enumerate()
+        .map(|(i, tab)| {
+            let marker = if i == app.active_tab() { "*" } else { " " };
             Spans::from(Span::styled(
-                t.to_string(),
-                Style::default().fg(Color::Green),
+                format!(
+                    "{}{} {}{}",
+                    i + 1,
+                    marker,
+                    tab.display_dir(app.is_physical_paths()),
+                    tab.git_branch()
+                        .map(|branch| format!(" ({})", branch))
+                        .unwrap_or_default()
+                ),
+                theme.header,
             ))
         })
         .collect();
     let tabs = Tabs::new(titles)
-        .block(Block::default().borders(Borders::ALL).title("Walker"))
-        .highlight_style(Style::default().fg(Color::Yellow))
-        .select(0);
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border)
+                .title("Walker"),
+        )
+        .highlight_style(theme.active_tab)
+        .select(app.active_tab());
     f.render_widget(tabs, chunks[0]);
 
-    let rows: Vec<_> = app
-        .main_panel()
-        .current_contents()
-        .iter()
-        .map(|f| -> Row {
-            Row::new(vec![
-                Cell::from(Span::raw(f.name.to_string())),
-                Cell::from(Span::raw(f.perms.to_string())),
-                Cell::from(Span::raw(
-                    f.size.file_size(options::DECIMAL).unwrap_or_default(),
-                )),
-            ])
-        })
-        .collect();
-
-    let body_chunks = if app.input_mode().is_copy() {
+    let body_chunks = if app.shows_action_panel() {
         Layout::default()
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
             .direction(Direction::Horizontal)
@@ -243,118 +545,730 @@ fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) -> Result<()> {
             .split(chunks[1])
     };
 
-    let file_table = Table::new(rows)
-        .widths(&[
-            Constraint::Percentage(75),
-            Constraint::Percentage(12),
-            Constraint::Percentage(12),
-        ])
-        .column_spacing(10)
-        .highlight_style(
-            Style::default()
-                .fg(Color::Rgb(0, 0, 0))
-                .bg(Color::Rgb(0, 125, 255))
-                .add_modifier(Modifier::BOLD),
+    let spinner = app.spinner();
+    let physical = app.is_physical_paths();
+    if app.shows_action_panel() {
+        let active = app.active_panel();
+        draw_panel(
+            f,
+            app.main_panel_mut(),
+            &theme,
+            body_chunks[0],
+            Some(active == PanelKind::Main),
+            spinner,
+            physical,
         );
-    // f.render_stateful_widget(file_table, body_chunks[0], app.directory_table_state_mut());
-    f.render_stateful_widget(
-        file_table,
-        body_chunks[0],
-        app.main_panel_mut().directory_table_state_mut(),
-    );
+        draw_panel(
+            f,
+            app.action_panel_mut(),
+            &theme,
+            body_chunks[1],
+            Some(active == PanelKind::Secondary),
+            spinner,
+            physical,
+        );
+    } else {
+        draw_panel(
+            f,
+            app.main_panel_mut(),
+            &theme,
+            body_chunks[0],
+            None,
+            spinner,
+            physical,
+        );
+    }
+
+    if app.is_bookmark_manager_open() {
+        draw_bookmark_manager(f, app, &theme, chunks[1]);
+    }
+
+    if let InputMode::Editing(EditingKind::Jump) = app.input_mode() {
+        draw_jump_candidates(f, app, &theme, chunks[1]);
+    }
+
+    if let InputMode::Editing(EditingKind::PatternRename) = app.input_mode() {
+        draw_pattern_preview(f, app, &theme, chunks[1]);
+    }
+
+    if let Some(capture) = app.running_command() {
+        draw_running_command(f, capture, app.spinner(), &theme, chunks[1]);
+    }
 
-    if app.input_mode().is_copy() {
-        // let selected_dir = app
-        //     .main_panel()
-        //     .selected_item()
-        //     .map_or(String::new(), |i| i.name.clone());
-        // app.action_panel_mut().set_current_dir(&selected_dir);
-        let action_rows: Vec<_> = app
-            .action_panel()
-            .current_contents()
-            .iter()
-            .map(|f| -> Row {
+    if let Some(output) = app.command_output() {
+        draw_command_output(f, output, &theme, chunks[1]);
+    }
+
+    if let Some(dialog) = app.chmod_dialog() {
+        draw_chmod_dialog(f, dialog, &theme, chunks[1]);
+    }
+
+    if let Some(diff) = app.file_diff() {
+        draw_file_diff(f, diff, &theme, chunks[1]);
+    }
+
+    if let Some(comparison) = app.dir_comparison_mut() {
+        draw_dir_comparison(f, comparison, &theme, chunks[1]);
+    }
+
+    if let Some(preview) = app.rename_preview_mut() {
+        draw_rename_preview(f, preview, &theme, chunks[1]);
+    }
+
+    let width = chunks[0].width.max(3) - 3; // keep 2 for borders and 1 for cursor
+    let scroll = (app.text_input().cursor() as u16).max(width) - width;
+    let editing_kind = match app.input_mode() {
+        InputMode::Editing(kind) if !app.is_browsing_destination() => Some(kind),
+        _ => None,
+    };
+    if let Some(kind) = editing_kind {
+        let input = Paragraph::new(app.text_input().value())
+            .style(Style::default().fg(Color::Yellow))
+            .scroll((0, scroll))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(theme.border)
+                    .title(kind.title()),
+            );
+        f.render_widget(input, chunks[2]);
+    } else {
+        let (title, text) = match app.input_mode() {
+            InputMode::Editing(kind) => (
+                kind.title(),
+                format!(
+                    "{} -> {}  (Enter to confirm, Esc to cancel)",
+                    app.main_panel().file_to_edit().name,
+                    app.action_panel().display_dir(app.is_physical_paths())
+                ),
+            ),
+            InputMode::Normal => ("Normal", app.status().unwrap_or_default().to_string()),
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.border)
+            .title(Span::styled(
+                title,
+                Style::default()
+                    .fg(Color::Magenta)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        let paragraph = Paragraph::new(vec![Spans::from(text)])
+            .block(block)
+            .wrap(Wrap { trim: true });
+        f.render_widget(paragraph, chunks[2]);
+    }
+
+    // Hide the cursor unless editing. `Frame` does this by default, so we don't need to do anything there
+    if editing_kind.is_some() {
+        // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
+        f.set_cursor(
+            // Put cursor past the end of the input text
+            chunks[2].x + (app.text_input().cursor() as u16).min(width) + 1,
+            // Move one line down, from the border to the input line
+            chunks[2].y + 1,
+        )
+    }
+    Ok(())
+}
+
+/// Draws a panel's listing. `focus` is `None` when the panel fills the body,
+/// otherwise it is framed and titled with its directory. Sizes still being
+/// computed show `spinner`; those not asked for stay blank.
+fn draw_panel<B: Backend>(
+    f: &mut Frame<B>,
+    view: &mut WalkerView,
+    theme: &Theme,
+    area: Rect,
+    focus: Option<bool>,
+    spinner: char,
+    physical: bool,
+) {
+    let dir = view.display_dir(physical).to_string();
+    let total = view.total_size();
+    let in_repo = view.git_repo().is_some();
+    let scanned: HashSet<String> = view.pending_dir_sizes().cloned().collect();
+    let rows: Vec<_> = view
+        .current_contents()
+        .iter()
+        .map(|item| -> Row {
+            let mut name = item.name.to_string();
+            if let Some(target) = &item.link_target {
+                name.push_str(&format!(" -> {}", target.display()));
+                if item.kind == ItemKind::BrokenSymlink {
+                    name.push_str(" (broken)");
+                }
+            }
+            let name = if view.is_marked(item) {
+                Span::styled(
+                    format!("+ {}", name),
+                    theme.style_for(item).patch(theme.marked),
+                )
+            } else {
+                Span::styled(name, theme.style_for(item))
+            };
+            let size = if scanned.contains(&item.name) {
+                spinner.to_string()
+            } else if item.size_pending {
+                String::new()
+            } else {
+                item.size.file_size(options::DECIMAL).unwrap_or_default()
+            };
+            if view.is_disk_usage() {
                 Row::new(vec![
-                    Cell::from(Span::raw(f.name.to_string())),
-                    Cell::from(Span::raw(f.perms.to_string())),
-                    Cell::from(Span::raw(
-                        f.size.file_size(options::DECIMAL).unwrap_or_default(),
-                    )),
+                    Cell::from(Span::raw(size)),
+                    Cell::from(Span::raw(usage_bar(item.size, total))),
+                    Cell::from(name),
                 ])
-            })
-            .collect();
+            } else if in_repo {
+                let git = match item.git_status {
+                    Some(status) => {
+                        Span::styled(status.symbol().to_string(), theme.git_style(status))
+                    }
+                    None => Span::raw(" "),
+                };
+                Row::new(vec![
+                    Cell::from(git),
+                    Cell::from(name),
+                    Cell::from(Span::raw(item.perms.to_string())),
+                    Cell::from(Span::raw(size)),
+                ])
+            } else {
+                Row::new(vec![
+                    Cell::from(name),
+                    Cell::from(Span::raw(item.perms.to_string())),
+                    Cell::from(Span::raw(size)),
+                ])
+            }
+        })
+        .collect();
 
-        let action_table = Table::new(action_rows)
+    let table = if view.is_disk_usage() {
+        Table::new(rows)
             .widths(&[
+                Constraint::Length(10),
+                Constraint::Length(19),
                 Constraint::Percentage(75),
+            ])
+            .column_spacing(2)
+    } else if in_repo {
+        Table::new(rows)
+            .widths(&[
+                Constraint::Length(1),
+                Constraint::Percentage(68),
                 Constraint::Percentage(12),
                 Constraint::Percentage(12),
             ])
-            .column_spacing(10)
-            .highlight_style(
-                Style::default()
-                    .fg(Color::Rgb(0, 0, 0))
-                    .bg(Color::Rgb(0, 125, 255))
-                    .add_modifier(Modifier::BOLD),
-            );
+            .column_spacing(2)
+    } else {
+        Table::new(rows)
+            .widths(&[
+                Constraint::Percentage(70),
+                Constraint::Percentage(12),
+                Constraint::Percentage(12),
+            ])
+            .column_spacing(2)
+    }
+    .highlight_style(theme.highlight);
 
-        f.render_stateful_widget(
-            action_table,
-            body_chunks[1],
-            app.action_panel_mut().directory_table_state_mut(),
+    let title = if view.is_disk_usage() {
+        let scanning = !scanned.is_empty();
+        Some(format!(
+            "Disk usage of {}: {}{}",
+            dir,
+            total.file_size(options::DECIMAL).unwrap_or_default(),
+            if scanning { " (scanning)" } else { "" }
+        ))
+    } else {
+        focus.map(|_| dir)
+    };
+    let table = match title {
+        Some(title) => {
+            let title_style = if focus.unwrap_or(true) {
+                theme.active_tab
+            } else {
+                theme.header
+            };
+            table.block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(theme.border)
+                    .title(Span::styled(title, title_style)),
+            )
+        }
+        None => table,
+    };
+
+    f.render_stateful_widget(table, area, view.directory_table_state_mut());
+}
+
+/// `size` as a percentage of `total` followed by a bar of `#`s, like ncdu.
+fn usage_bar(size: u64, total: u64) -> String {
+    const WIDTH: usize = 10;
+    let fraction = if total == 0 {
+        0.0
+    } else {
+        size as f64 / total as f64
+    };
+    let filled = (fraction * WIDTH as f64).round() as usize;
+    format!(
+        "{:5.1}% [{}{}]",
+        fraction * 100.0,
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled)
+    )
+}
+
+fn draw_bookmark_manager<B: Backend>(f: &mut Frame<B>, app: &mut App, theme: &Theme, area: Rect) {
+    let rows: Vec<_> = app
+        .bookmarks()
+        .entries()
+        .iter()
+        .map(|b| {
+            Row::new(vec![
+                Cell::from(Span::styled(b.key.to_string(), theme.active_tab)),
+                Cell::from(Span::raw(b.name.clone())),
+                Cell::from(Span::styled(b.path.clone(), theme.directory)),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(rows)
+        .header(Row::new(vec!["Key", "Name", "Path"]).style(theme.header))
+        .widths(&[
+            Constraint::Length(3),
+            Constraint::Percentage(30),
+            Constraint::Percentage(65),
+        ])
+        .column_spacing(2)
+        .highlight_style(theme.highlight)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border)
+                .title("Bookmarks (Enter jump, r rename, x delete, Esc close)"),
         );
+
+    let popup = centered_rect(70, 60, area);
+    f.render_widget(Clear, popup);
+    if let Some(table_state) = app.bookmark_manager_state_mut() {
+        f.render_stateful_widget(table, popup, table_state);
     }
-    // f.render_stateful_widget(file_table, body_chunks[1], app.directory_table_state_mut());
+}
 
-    let width = chunks[0].width.max(3) - 3; // keep 2 for borders and 1 for cursor
-    let scroll = (app.text_input().cursor() as u16).max(width) - width;
-    if app.input_mode().is_renaming() {
-        // let text = vec![Spans::from(app.file_to_edit.clone())];
-        let input = Paragraph::new(app.text_input().value())
-            .style(match app.input_mode() {
-                InputMode::Normal => Style::default(),
-                InputMode::Editing(_) => Style::default().fg(Color::Yellow),
-            })
-            .scroll((0, scroll))
-            .block(Block::default().borders(Borders::ALL).title("Rename"));
-        // let block = Block::default().borders(Borders::ALL).title(Span::styled(
-        //     "Rename",
-        //     Style::default()
-        //         .fg(Color::Magenta)
-        //         .add_modifier(Modifier::BOLD),
-        // ));
-        // f.render_widget(paragraph, chunks[2]);
-        f.render_widget(input, chunks[2]);
+fn draw_jump_candidates<B: Backend>(f: &mut Frame<B>, app: &App, theme: &Theme, area: Rect) {
+    let rows: Vec<_> = app
+        .jump_candidates()
+        .iter()
+        .map(|dir| {
+            Row::new(vec![Cell::from(Span::styled(
+                dir.as_str(),
+                theme.directory,
+            ))])
+        })
+        .collect();
+
+    let table = Table::new(rows)
+        .widths(&[Constraint::Percentage(100)])
+        .highlight_style(theme.highlight)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border)
+                .title("Frecent directories (Up/Down select, Enter jump)"),
+        );
+
+    let mut table_state = TableState::default();
+    table_state.select(Some(app.jump_selected()));
+    let popup = centered_rect(70, 60, area);
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(table, popup, &mut table_state);
+}
+
+fn draw_command_output<B: Backend>(
+    f: &mut Frame<B>,
+    output: &CommandOutput,
+    theme: &Theme,
+    area: Rect,
+) {
+    let text = if output.diff {
+        Text::from(
+            output
+                .text
+                .lines()
+                .map(|line| Spans::from(Span::styled(line, diff_line_style(line, theme))))
+                .collect::<Vec<_>>(),
+        )
     } else {
-        let text = vec![Spans::from("")];
-        let block = Block::default().borders(Borders::ALL).title(Span::styled(
-            "Normal",
-            Style::default()
-                .fg(Color::Magenta)
-                .add_modifier(Modifier::BOLD),
+        Text::raw(output.text.as_str())
+    };
+    let paragraph = Paragraph::new(text)
+        .scroll((output.scroll, 0))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border)
+                .title(Span::styled(
+                    format!("$ {} (j/k scroll, Esc close)", output.command),
+                    theme.header,
+                )),
+        );
+
+    let popup = centered_rect(80, 80, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+fn draw_running_command<B: Backend>(
+    f: &mut Frame<B>,
+    capture: &Capture,
+    spinner: char,
+    theme: &Theme,
+    area: Rect,
+) {
+    let paragraph = Paragraph::new(format!("{} Running...", spinner)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.border)
+            .title(Span::styled(
+                format!("$ {} (Esc kills it)", capture.command),
+                theme.header,
+            )),
+    );
+
+    let popup = centered_rect(60, 20, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+fn draw_file_diff<B: Backend>(f: &mut Frame<B>, diff: &FileDiff, theme: &Theme, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(theme.border)
+        .title(Span::styled(
+            format!(
+                "{} | {} (v {}, j/k scroll, Esc close)",
+                diff.left.display(),
+                diff.right.display(),
+                if diff.side_by_side {
+                    "unified"
+                } else {
+                    "side by side"
+                }
+            ),
+            theme.header,
         ));
-        let paragraph = Paragraph::new(text).block(block).wrap(Wrap { trim: true });
-        f.render_widget(paragraph, chunks[2]);
+    let popup = centered_rect(90, 80, area);
+    f.render_widget(Clear, popup);
+
+    if !diff.side_by_side {
+        let lines: Vec<_> = diff
+            .unified
+            .lines()
+            .map(|line| Spans::from(Span::styled(line, diff_line_style(line, theme))))
+            .collect();
+        let paragraph = Paragraph::new(lines).scroll((diff.scroll, 0)).block(block);
+        f.render_widget(paragraph, popup);
+        return;
     }
 
-    match app.input_mode() {
-        InputMode::Editing(EditingKind::Rename) => {
-            // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
-            f.set_cursor(
-                // Put cursor past the end of the input text
-                chunks[2].x + (app.text_input().cursor() as u16).min(width) + 1,
-                // Move one line down, from the border to the input line
-                chunks[2].y + 1,
-            )
+    let removed = theme.diff_removed;
+    let added = theme.diff_added;
+    let rows: Vec<_> = diff
+        .rows
+        .iter()
+        .skip(diff.scroll as usize)
+        .map(|row| match row {
+            Some(row) => {
+                let (left_style, gutter, right_style) = match row.kind {
+                    RowKind::Same => (Style::default(), " ", Style::default()),
+                    RowKind::Removed => (removed, "<", Style::default()),
+                    RowKind::Added => (Style::default(), ">", added),
+                    RowKind::Changed => (removed, "|", added),
+                };
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        row.left.clone().unwrap_or_default(),
+                        left_style,
+                    )),
+                    Cell::from(Span::raw(gutter)),
+                    Cell::from(Span::styled(
+                        row.right.clone().unwrap_or_default(),
+                        right_style,
+                    )),
+                ])
+            }
+            None => Row::new(vec![
+                Cell::from(Span::styled("...", theme.header)),
+                Cell::from(Span::raw("")),
+                Cell::from(Span::styled("...", theme.header)),
+            ]),
+        })
+        .collect();
+    let table = Table::new(rows)
+        .widths(&[
+            Constraint::Percentage(48),
+            Constraint::Length(1),
+            Constraint::Percentage(48),
+        ])
+        .column_spacing(1)
+        .block(block);
+    f.render_widget(table, popup);
+}
+
+fn draw_dir_comparison<B: Backend>(
+    f: &mut Frame<B>,
+    comparison: &mut DirComparison,
+    theme: &Theme,
+    area: Rect,
+) {
+    let describe = |meta: &Option<std::fs::Metadata>| match meta {
+        Some(meta) if meta.is_dir() => "directory".to_string(),
+        Some(meta) => format!(
+            "{} {}",
+            meta.len().file_size(options::DECIMAL).unwrap_or_default(),
+            meta.modified()
+                .map(|time| DateTime::<Local>::from(time)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string())
+                .unwrap_or_default()
+        ),
+        None => String::new(),
+    };
+    let rows: Vec<_> = comparison
+        .entries
+        .iter()
+        .map(|entry| {
+            Row::new(vec![
+                Cell::from(Span::styled(entry.difference.label(), theme.marked)),
+                Cell::from(Span::raw(entry.name.clone())),
+                Cell::from(Span::raw(describe(&entry.left))),
+                Cell::from(Span::raw(describe(&entry.right))),
+            ])
+        })
+        .collect();
+
+    let question = comparison
+        .pending_sync
+        .and_then(|direction| comparison.sync_question(direction));
+    let title = match question {
+        Some(question) => Span::styled(question, theme.marked.add_modifier(Modifier::BOLD)),
+        None => Span::styled(
+            format!(
+                "{} | {}: {} different, {} the same by {} (s sync, > to right, < to left, c compare {}, Esc close)",
+                comparison.left.display(),
+                comparison.right.display(),
+                comparison.entries.len(),
+                comparison.identical,
+                if comparison.by_content { "content" } else { "size and time" },
+                if comparison.by_content { "size and time" } else { "content" },
+            ),
+            theme.header,
+        ),
+    };
+
+    let table = Table::new(rows)
+        .header(Row::new(vec!["", "Name", "Left", "Right"]).style(theme.header))
+        .widths(&[
+            Constraint::Length(11),
+            Constraint::Percentage(36),
+            Constraint::Percentage(26),
+            Constraint::Percentage(26),
+        ])
+        .column_spacing(1)
+        .highlight_style(theme.highlight)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border)
+                .title(title),
+        );
+
+    let popup = centered_rect(90, 80, area);
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(table, popup, &mut comparison.table_state);
+}
+
+/// Styles additions, removals and hunk headers with the theme, like
+/// `git diff --color`.
+fn diff_line_style(line: &str, theme: &Theme) -> Style {
+    if line.starts_with("+++") || line.starts_with("---") || line.starts_with('#') {
+        theme.header.add_modifier(Modifier::BOLD)
+    } else if line.starts_with('+') {
+        theme.diff_added
+    } else if line.starts_with('-') {
+        theme.diff_removed
+    } else if line.starts_with("@@") {
+        theme.diff_hunk
+    } else {
+        Style::default()
+    }
+}
+
+fn draw_rename_preview<B: Backend>(
+    f: &mut Frame<B>,
+    preview: &mut RenamePreview,
+    theme: &Theme,
+    area: Rect,
+) {
+    let rows: Vec<_> = preview
+        .renames
+        .iter()
+        .map(|(from, to)| {
+            Row::new(vec![
+                Cell::from(Span::raw(file_name(from))),
+                Cell::from(Span::raw("->")),
+                Cell::from(Span::styled(file_name(to), theme.marked)),
+            ])
+        })
+        .collect();
+
+    let title = format!(
+        "Rename {} entries? (Enter apply, Esc cancel)",
+        preview.renames.len()
+    );
+    let table = Table::new(rows)
+        .widths(&[
+            Constraint::Percentage(47),
+            Constraint::Length(2),
+            Constraint::Percentage(47),
+        ])
+        .column_spacing(1)
+        .highlight_style(theme.highlight)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border)
+                .title(Span::styled(title, theme.header)),
+        );
+
+    let popup = centered_rect(80, 70, area);
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(table, popup, &mut preview.table_state);
+}
+
+/// The live preview of a pattern rename, with the first problem in the title.
+fn draw_pattern_preview<B: Backend>(f: &mut Frame<B>, app: &App, theme: &Theme, area: Rect) {
+    let rows: Vec<_> = app
+        .pattern_renames()
+        .iter()
+        .map(|(old, new)| {
+            let style = if old == new {
+                Style::default()
+            } else {
+                theme.marked
+            };
+            Row::new(vec![
+                Cell::from(Span::raw(old.clone())),
+                Cell::from(Span::raw("->")),
+                Cell::from(Span::styled(new.clone(), style)),
+            ])
+        })
+        .collect();
+
+    let title = match app.pattern_rename_plan() {
+        Ok(plan) => Span::styled(
+            format!("{} to rename (Enter apply, Esc cancel)", plan.len()),
+            theme.header,
+        ),
+        Err(e) => Span::styled(format!("{:#}", e), Style::default().fg(Color::Red)),
+    };
+    let table = Table::new(rows)
+        .widths(&[
+            Constraint::Percentage(47),
+            Constraint::Length(2),
+            Constraint::Percentage(47),
+        ])
+        .column_spacing(1)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border)
+                .title(title),
+        );
+
+    let popup = centered_rect(80, 70, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(table, popup);
+}
+
+fn draw_chmod_dialog<B: Backend>(
+    f: &mut Frame<B>,
+    dialog: &ChmodDialog,
+    theme: &Theme,
+    area: Rect,
+) {
+    let mut lines = vec![
+        Spans::from(vec![
+            Span::styled(format!("{:04o}", dialog.mode), theme.marked),
+            Span::raw(format!("  {}", &unix_mode::to_string(dialog.mode)[1..])),
+        ]),
+        Spans::from(""),
+    ];
+    for (row, label) in perms::ROWS.iter().enumerate() {
+        let mut spans = vec![Span::raw(format!("{:<9}", label))];
+        for (column, bit) in perms::COLUMNS[row].iter().enumerate() {
+            let check = if dialog.is_set(row, column) { "x" } else { " " };
+            let style = if (row, column) == (dialog.row, dialog.column) {
+                theme.highlight
+            } else {
+                Style::default()
+            };
+            spans.push(Span::styled(format!("[{}] {:<7}", check, bit), style));
         }
-        // Hide the cursor. `Frame` does this by default, so we don't need to do anything here
-        _ => {}
+        lines.push(Spans::from(spans));
     }
-    // match app.tabs.index {
-    //     0 => draw_first_tab(f, app, chunks[1]),
-    //     1 => draw_second_tab(f, app, chunks[1]),
-    //     2 => draw_third_tab(f, app, chunks[1]),
-    //     _ => {}
-    // };
-    Ok(())
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(format!(
+        "Recursive into directories: {}",
+        if dialog.recursive { "yes" } else { "no" }
+    )));
+    lines.push(Spans::from(Span::styled(
+        "Space toggle, 0-7 octal, r recursive, Enter apply, Esc cancel",
+        theme.header,
+    )));
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.border)
+            .title(format!("chmod {} entries", dialog.targets.len())),
+    );
+    let popup = centered_rect(60, 50, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or(path.display().to_string(), |name| {
+        name.to_string_lossy().to_string()
+    })
+}
+
+/// A rectangle of `percent_x` by `percent_y` of `area`, centred in it.
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_y) / 2),
+                Constraint::Percentage(percent_y),
+                Constraint::Percentage((100 - percent_y) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_x) / 2),
+                Constraint::Percentage(percent_x),
+                Constraint::Percentage((100 - percent_x) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(vertical[1])[1]
 }
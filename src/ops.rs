@@ -0,0 +1,194 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Copies `src` (recursively, for directories) into `dest_dir`, keeping its
+/// file name. Returns the path of the copy.
+pub fn copy_into(src: &Path, dest_dir: &Path) -> Result<PathBuf> {
+    let dest = destination(src, dest_dir)?;
+    if dest.starts_with(src) {
+        bail!("cannot copy {} into itself", src.display());
+    }
+
+    copy_recursive(src, &dest)
+        .with_context(|| format!("failed to copy {} to {}", src.display(), dest.display()))?;
+    Ok(dest)
+}
+
+/// Moves `src` into `dest_dir`, falling back to copy and delete when the
+/// two are on different filesystems. Returns the new path.
+pub fn move_into(src: &Path, dest_dir: &Path) -> Result<PathBuf> {
+    let dest = destination(src, dest_dir)?;
+    if dest.starts_with(src) {
+        bail!("cannot move {} into itself", src.display());
+    }
+
+    match fs::rename(src, &dest) {
+        Ok(()) => {}
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            copy_recursive(src, &dest).with_context(|| {
+                format!("failed to move {} to {}", src.display(), dest.display())
+            })?;
+            remove_path(src).with_context(|| {
+                format!(
+                    "copied {} to {} but could not remove the original, so both exist",
+                    src.display(),
+                    dest.display()
+                )
+            })?;
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to move {} to {}", src.display(), dest.display()))
+        }
+    }
+    Ok(dest)
+}
+
+/// Removes a file, symlink or directory tree.
+pub fn remove_path(path: &Path) -> Result<()> {
+    let meta =
+        fs::symlink_metadata(path).with_context(|| format!("failed to read {}", path.display()))?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("failed to remove {}", path.display()))
+}
+
+/// Creates the directory `path` along with any missing parents.
+pub fn create_dir(path: &Path) -> Result<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        bail!("{} already exists", path.display());
+    }
+    fs::create_dir_all(path).with_context(|| format!("failed to create {}", path.display()))
+}
+
+/// Creates the empty file `path` along with any missing parent directories.
+pub fn create_file(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map(|_| ())
+        .with_context(|| format!("failed to create {}", path.display()))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A symlink holding the absolute path of the source.
+    Absolute,
+    /// A symlink holding the path of the source relative to the link.
+    Relative,
+    Hard,
+}
+
+/// Links `src` into `dest_dir` under the same file name. `src` and
+/// `dest_dir` must be absolute. Returns the path of the link.
+pub fn link_into(src: &Path, dest_dir: &Path, kind: LinkKind) -> Result<PathBuf> {
+    let dest = destination(src, dest_dir)?;
+    let result = match kind {
+        LinkKind::Absolute => std::os::unix::fs::symlink(src, &dest),
+        LinkKind::Relative => std::os::unix::fs::symlink(relative_path(src, dest_dir), &dest),
+        LinkKind::Hard => fs::hard_link(src, &dest),
+    };
+    result.with_context(|| format!("failed to link {} to {}", dest.display(), src.display()))?;
+    Ok(dest)
+}
+
+/// The path that leads from the directory `from` to `to`, both absolute and
+/// compared component by component, without resolving symlinks.
+fn relative_path(to: &Path, from: &Path) -> PathBuf {
+    let to: Vec<Component> = to.components().collect();
+    let from: Vec<Component> = from.components().collect();
+    let common = to.iter().zip(&from).take_while(|(a, b)| a == b).count();
+
+    let mut path: PathBuf = from[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .collect();
+    path.extend(&to[common..]);
+    path
+}
+
+fn destination(src: &Path, dest_dir: &Path) -> Result<PathBuf> {
+    if !dest_dir.is_dir() {
+        bail!("{} is not a directory", dest_dir.display());
+    }
+
+    let name = src
+        .file_name()
+        .ok_or_else(|| anyhow!("{} has no file name", src.display()))?;
+    let dest = dest_dir.join(name);
+    if fs::symlink_metadata(&dest).is_ok() {
+        bail!("{} already exists", dest.display());
+    }
+
+    Ok(dest)
+}
+
+fn copy_recursive(src: &Path, dest: &Path) -> std::io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    if meta.file_type().is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(src)?, dest)
+    } else if meta.is_dir() {
+        fs::create_dir(dest)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &dest.join(entry.file_name()))?;
+        }
+        fs::set_permissions(dest, meta.permissions())
+    } else {
+        fs::copy(src, dest).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_into_renames_within_a_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("file");
+        let dest_dir = dir.path().join("dest");
+        fs::write(&src, "contents").unwrap();
+        fs::create_dir(&dest_dir).unwrap();
+
+        let dest = move_into(&src, &dest_dir).unwrap();
+
+        assert_eq!(dest, dest_dir.join("file"));
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest).unwrap(), "contents");
+    }
+
+    #[test]
+    fn move_into_reports_rename_errors_without_copying() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("missing");
+        let dest_dir = dir.path().join("dest");
+        fs::create_dir(&dest_dir).unwrap();
+
+        let error = move_into(&src, &dest_dir).unwrap_err();
+
+        let io = error.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(io.kind(), std::io::ErrorKind::NotFound);
+        assert!(fs::read_dir(&dest_dir).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn relative_path_climbs_to_the_common_ancestor() {
+        assert_eq!(
+            relative_path(Path::new("/a/b/file"), Path::new("/a/c/d")),
+            PathBuf::from("../../b/file")
+        );
+    }
+}
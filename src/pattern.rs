@@ -0,0 +1,293 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use regex::{Regex, RegexBuilder};
+
+use crate::app::Item;
+use crate::listing;
+
+/// How `C` renames the marked entries: a sed-like `s/regex/replacement/flags`
+/// substitution on the name, or a template such as `{n:03}_{stem}{.ext}`.
+#[derive(Debug)]
+pub enum RenamePattern {
+    Substitute {
+        regex: Regex,
+        replacement: String,
+        global: bool,
+    },
+    Template(String),
+}
+
+impl RenamePattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        match pattern.strip_prefix("s/") {
+            Some(rest) => parse_substitution(rest),
+            None => {
+                // Expand once up front so mistakes show before anything is renamed.
+                expand_template(pattern, &Item::default(), 1)?;
+                Ok(RenamePattern::Template(pattern.to_string()))
+            }
+        }
+    }
+
+    /// The new name of `item`, the `n`th (from 1) entry being renamed.
+    pub fn apply(&self, item: &Item, n: usize) -> Result<String> {
+        match self {
+            RenamePattern::Substitute {
+                regex,
+                replacement,
+                global,
+            } => {
+                let name = listing::file_name(item);
+                let renamed = if *global {
+                    regex.replace_all(name, replacement.as_str())
+                } else {
+                    regex.replace(name, replacement.as_str())
+                };
+                Ok(renamed.to_string())
+            }
+            RenamePattern::Template(template) => expand_template(template, item, n),
+        }
+    }
+}
+
+/// Parses the part after `s/`, where `\/` is a literal slash. Flags are `g`
+/// to replace every match and `i` to ignore case.
+fn parse_substitution(rest: &str) -> Result<RenamePattern> {
+    let mut parts = vec![String::new()];
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('/') => parts.last_mut().unwrap().push('/'),
+                Some(other) => {
+                    let part = parts.last_mut().unwrap();
+                    part.push('\\');
+                    part.push(other);
+                }
+                None => parts.last_mut().unwrap().push('\\'),
+            },
+            '/' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+
+    let (regex, replacement, flags) = match parts.as_slice() {
+        [regex, replacement] => (regex, replacement, ""),
+        [regex, replacement, flags] => (regex, replacement, flags.as_str()),
+        _ => bail!("expected s/regex/replacement/ with optional flags g and i"),
+    };
+    if let Some(flag) = flags.chars().find(|c| !matches!(c, 'g' | 'i')) {
+        bail!("unknown flag '{}'", flag);
+    }
+
+    let regex = RegexBuilder::new(regex)
+        .case_insensitive(flags.contains('i'))
+        .build()?;
+    Ok(RenamePattern::Substitute {
+        regex,
+        replacement: replacement.clone(),
+        global: flags.contains('g'),
+    })
+}
+
+/// Expands the `{field}` and `{field:spec}` placeholders of `template`;
+/// `{{` and `}}` are literal braces.
+///
+/// - `{name}`, `{stem}`, `{ext}` and `{.ext}` (the extension with its dot,
+///   or nothing) take `upper`, `lower` or `title` as spec.
+/// - `{n}` counts from 1; `{n:03}` pads it to three digits.
+/// - `{date}` is the modification date, `{date:%Y%m%d}` in any strftime format.
+fn expand_template(template: &str, item: &Item, n: usize) -> Result<String> {
+    let mut expanded = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                expanded.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                expanded.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => bail!("unclosed '{{'"),
+                    }
+                }
+                expanded.push_str(&expand_field(&placeholder, item, n)?);
+            }
+            '}' => bail!("unmatched '}}'; write '}}}}' for a literal brace"),
+            c => expanded.push(c),
+        }
+    }
+    Ok(expanded)
+}
+
+fn expand_field(placeholder: &str, item: &Item, n: usize) -> Result<String> {
+    let (field, spec) = match placeholder.split_once(':') {
+        Some((field, spec)) => (field, Some(spec)),
+        None => (placeholder, None),
+    };
+
+    let name = listing::file_name(item);
+    let path = Path::new(name);
+    let stem = path
+        .file_stem()
+        .map_or(String::new(), |s| s.to_string_lossy().to_string());
+    let ext = path
+        .extension()
+        .map_or(String::new(), |e| e.to_string_lossy().to_string());
+
+    let text = match field {
+        "n" => {
+            let width: usize = match spec {
+                Some(spec) => spec
+                    .parse()
+                    .map_err(|_| anyhow!("{{n:{}}}: expected a width such as 03", spec))?,
+                None => 0,
+            };
+            return Ok(format!("{:0width$}", n, width = width));
+        }
+        "date" => {
+            let format = spec.unwrap_or("%Y-%m-%d");
+            if chrono::format::StrftimeItems::new(format)
+                .any(|item| matches!(item, chrono::format::Item::Error))
+            {
+                bail!("{{date:{}}}: invalid date format", format);
+            }
+            return Ok(item.modified_date.format(format).to_string());
+        }
+        "name" => name.to_string(),
+        "stem" => stem,
+        "ext" => ext,
+        ".ext" if ext.is_empty() => ext,
+        ".ext" => format!(".{}", ext),
+        _ => bail!("unknown field {{{}}}", field),
+    };
+
+    match spec {
+        None => Ok(text),
+        Some("upper") => Ok(text.to_uppercase()),
+        Some("lower") => Ok(text.to_lowercase()),
+        Some("title") => Ok(title_case(&text)),
+        Some(spec) => bail!("{{{}:{}}}: expected upper, lower or title", field, spec),
+    }
+}
+
+/// Capitalises the first letter of every word, lowering the rest.
+fn title_case(text: &str) -> String {
+    let mut titled = String::new();
+    let mut word_start = true;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            if word_start {
+                titled.extend(c.to_uppercase());
+            } else {
+                titled.extend(c.to_lowercase());
+            }
+            word_start = false;
+        } else {
+            titled.push(c);
+            word_start = true;
+        }
+    }
+    titled
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use super::*;
+
+    fn item(name: &str) -> Item {
+        Item {
+            name: format!("/photos/{}", name),
+            modified_date: Local.ymd(2021, 7, 4).and_hms(12, 0, 0),
+            ..Item::default()
+        }
+    }
+
+    fn rename(pattern: &str, name: &str, n: usize) -> String {
+        RenamePattern::parse(pattern)
+            .unwrap()
+            .apply(&item(name), n)
+            .unwrap()
+    }
+
+    fn parse_error(pattern: &str) -> String {
+        RenamePattern::parse(pattern).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn substitution_replaces_the_first_match_unless_global() {
+        assert_eq!(rename("s/o/0/", "foo.txt", 1), "f0o.txt");
+        assert_eq!(rename("s/o/0/g", "foo.txt", 1), "f00.txt");
+        assert_eq!(rename("s/O/0/gi", "foo.txt", 1), "f00.txt");
+        assert_eq!(rename("s/O/0/g", "foo.txt", 1), "foo.txt");
+    }
+
+    #[test]
+    fn substitution_supports_groups_and_escaped_slashes() {
+        assert_eq!(
+            rename(r"s/(\w+)\.(\w+)/$2.$1/", "photo.jpg", 1),
+            "jpg.photo"
+        );
+        assert_eq!(rename(r"s/_/\//", "a_b", 1), "a/b");
+        assert_eq!(rename(r"s/\//_/", "a", 1), "a");
+    }
+
+    #[test]
+    fn substitution_reports_bad_patterns() {
+        assert_eq!(
+            parse_error("s/a"),
+            "expected s/regex/replacement/ with optional flags g and i"
+        );
+        assert_eq!(parse_error("s/a/b/x"), "unknown flag 'x'");
+        assert!(parse_error("s/(/b/").contains("unclosed group"));
+    }
+
+    #[test]
+    fn template_expands_counters_and_name_parts() {
+        assert_eq!(
+            rename("{n:03}_{stem:lower}{.ext}", "Photo.JPG", 7),
+            "007_photo.JPG"
+        );
+        assert_eq!(rename("{n}-{name:upper}", "a b.txt", 12), "12-A B.TXT");
+        assert_eq!(
+            rename("{stem:title}.{ext}", "my old-file.md", 1),
+            "My Old-File.md"
+        );
+        assert_eq!(rename("{stem}{.ext}", "README", 1), "README");
+        assert_eq!(rename("{ext}", "README", 1), "");
+    }
+
+    #[test]
+    fn template_expands_dates_and_literal_braces() {
+        assert_eq!(rename("{date:%Y}_{name}", "a.txt", 1), "2021_a.txt");
+        assert_eq!(rename("{date}", "a.txt", 1), "2021-07-04");
+        assert_eq!(rename("{{{name}}}", "a.txt", 1), "{a.txt}");
+    }
+
+    #[test]
+    fn template_reports_mistakes_when_parsed() {
+        assert_eq!(parse_error("{name"), "unclosed '{'");
+        assert_eq!(
+            parse_error("name}"),
+            "unmatched '}'; write '}}' for a literal brace"
+        );
+        assert_eq!(parse_error("{size}"), "unknown field {size}");
+        assert_eq!(parse_error("{n:ab}"), "{n:ab}: expected a width such as 03");
+        assert_eq!(
+            parse_error("{stem:camel}"),
+            "{stem:camel}: expected upper, lower or title"
+        );
+        assert_eq!(parse_error("{date:%Q}"), "{date:%Q}: invalid date format");
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    ffi::{CStr, CString},
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use walkdir::WalkDir;
+
+use crate::command;
+
+/// Labels of the rows of the chmod grid; the last row holds setuid, setgid
+/// and sticky.
+pub const ROWS: [&str; 4] = ["user", "group", "other", "special"];
+pub const COLUMNS: [[&str; 3]; 4] = [
+    ["r", "w", "x"],
+    ["r", "w", "x"],
+    ["r", "w", "x"],
+    ["suid", "sgid", "sticky"],
+];
+
+/// The chmod dialog: a grid of permission bits that can be toggled, or set
+/// all at once by typing the mode in octal.
+#[derive(Clone, Debug)]
+pub struct ChmodDialog {
+    pub targets: Vec<String>,
+    pub mode: u32,
+    pub row: usize,
+    pub column: usize,
+    pub octal: String,
+    /// Whether marked directories get the mode all the way down.
+    pub recursive: bool,
+}
+
+impl ChmodDialog {
+    pub fn new(targets: Vec<String>, mode: u32, recursive: bool) -> Self {
+        Self {
+            targets,
+            mode: mode & 0o7777,
+            row: 0,
+            column: 0,
+            octal: String::new(),
+            recursive,
+        }
+    }
+
+    /// The bit under grid cell `row`, `column`.
+    pub fn bit(row: usize, column: usize) -> u32 {
+        if row == 3 {
+            0o4000 >> column
+        } else {
+            (0o400 >> column) >> (3 * row)
+        }
+    }
+
+    pub fn is_set(&self, row: usize, column: usize) -> bool {
+        self.mode & Self::bit(row, column) != 0
+    }
+
+    pub fn move_cursor(&mut self, rows: isize, columns: isize) {
+        self.row = (self.row as isize + rows).rem_euclid(ROWS.len() as isize) as usize;
+        self.column = (self.column as isize + columns).rem_euclid(3) as usize;
+    }
+
+    pub fn toggle(&mut self) {
+        self.mode ^= Self::bit(self.row, self.column);
+        self.octal.clear();
+    }
+
+    /// Adds an octal digit to the mode being typed, which replaces the mode
+    /// as soon as it parses.
+    pub fn push_digit(&mut self, digit: char) {
+        if digit.is_digit(8) && self.octal.len() < 4 {
+            self.octal.push(digit);
+            self.mode = u32::from_str_radix(&self.octal, 8).unwrap_or(self.mode);
+        }
+    }
+
+    pub fn pop_digit(&mut self) {
+        self.octal.pop();
+        if !self.octal.is_empty() {
+            self.mode = u32::from_str_radix(&self.octal, 8).unwrap_or(self.mode);
+        }
+    }
+}
+
+/// Sets the mode of `path`, and of everything below it when `recursive`.
+/// Symlinks inside the tree are skipped, as chmod would change their target.
+/// Directories in a recursive change can also be searched by whoever may
+/// read them, like chmod's `X`, so `644` leaves them usable as `755`.
+/// Returns how many entries changed.
+pub fn chmod(path: &Path, mode: u32, recursive: bool) -> Result<usize> {
+    let mut changed = 0;
+    for target in targets(path, recursive)? {
+        if target != path && target.is_symlink() {
+            continue;
+        }
+        let mode = if recursive && target.is_dir() {
+            searchable(mode)
+        } else {
+            mode
+        };
+        fs::set_permissions(&target, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("failed to change the mode of {}", target.display()))?;
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+/// `mode` with the execute bit set wherever the read bit is.
+fn searchable(mode: u32) -> u32 {
+    mode | (mode & 0o444) >> 2
+}
+
+/// Changes the owner and/or group of `path`, and of everything below it when
+/// `recursive`. Symlinks inside the tree are changed themselves.
+pub fn chown(path: &Path, uid: Option<u32>, gid: Option<u32>, recursive: bool) -> Result<usize> {
+    let mut changed = 0;
+    for target in targets(path, recursive)? {
+        if target == path {
+            std::os::unix::fs::chown(&target, uid, gid)
+        } else {
+            std::os::unix::fs::lchown(&target, uid, gid)
+        }
+        .with_context(|| format!("failed to change the owner of {}", target.display()))?;
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+/// `path`, preceded by everything below it when `recursive`, without
+/// following symlinks. Each directory comes after its contents, so a change
+/// that makes it unsearchable does not stop the ones below it.
+fn targets(path: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    if !recursive {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    WalkDir::new(path)
+        .contents_first(true)
+        .into_iter()
+        .map(|entry| {
+            entry
+                .map(|e| e.into_path())
+                .with_context(|| format!("failed to read {}", path.display()))
+        })
+        .collect()
+}
+
+/// Parses `owner`, `owner:group` or `:group`, by name or number.
+pub fn parse_owner(spec: &str) -> Result<(Option<u32>, Option<u32>)> {
+    let (user, group) = match spec.split_once(':') {
+        Some((user, group)) => (user, group),
+        None => (spec, ""),
+    };
+    let uid = match user {
+        "" => None,
+        user => Some(
+            user.parse()
+                .ok()
+                .or_else(|| user_id(user))
+                .ok_or_else(|| anyhow!("no user '{}'", user))?,
+        ),
+    };
+    let gid = match group {
+        "" => None,
+        group => Some(
+            group
+                .parse()
+                .ok()
+                .or_else(|| group_id(group))
+                .ok_or_else(|| anyhow!("no group '{}'", group))?,
+        ),
+    };
+    if uid.is_none() && gid.is_none() {
+        bail!("expected owner, owner:group or :group");
+    }
+    Ok((uid, gid))
+}
+
+/// The owner and group of `path` as `owner:group`, by name where known.
+pub fn owner_of(path: &Path) -> Option<String> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    Some(format!(
+        "{}:{}",
+        user_name(meta.uid()).unwrap_or_else(|| meta.uid().to_string()),
+        group_name(meta.gid()).unwrap_or_else(|| meta.gid().to_string())
+    ))
+}
+
+/// Completes the user name, or the group name after a `:`.
+pub fn complete_owner(spec: &str) -> Option<String> {
+    let names = |accounts: Vec<(String, u32)>| accounts.into_iter().map(|(name, _)| name).collect();
+    complete_owner_from(spec, || names(users()), || names(groups()))
+}
+
+/// `complete_owner` over the names `users` and `groups` list.
+fn complete_owner_from(
+    spec: &str,
+    users: impl FnOnce() -> Vec<String>,
+    groups: impl FnOnce() -> Vec<String>,
+) -> Option<String> {
+    match spec.split_once(':') {
+        Some((user, group)) => command::complete_word(group, groups().into_iter())
+            .map(|group| format!("{}:{}", user, group)),
+        None => command::complete_word(spec, users().into_iter()),
+    }
+}
+
+// The account lookups below go through NSS, so users and groups from LDAP,
+// systemd-homed and the like are found as well as those in /etc/passwd and
+// /etc/group. They return pointers into static storage, copied out at once;
+// only the UI thread calls them.
+
+fn user_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    (!entry.is_null()).then(|| unsafe { (*entry).pw_uid })
+}
+
+fn group_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    (!entry.is_null()).then(|| unsafe { (*entry).gr_gid })
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    let entry = unsafe { libc::getpwuid(uid) };
+    (!entry.is_null()).then(|| unsafe { c_string((*entry).pw_name) })
+}
+
+fn group_name(gid: u32) -> Option<String> {
+    let entry = unsafe { libc::getgrgid(gid) };
+    (!entry.is_null()).then(|| unsafe { c_string((*entry).gr_name) })
+}
+
+/// Every user name the system lists, sorted.
+fn users() -> Vec<(String, u32)> {
+    let mut entries = vec![];
+    unsafe {
+        libc::setpwent();
+        loop {
+            let entry = libc::getpwent();
+            if entry.is_null() {
+                break;
+            }
+            entries.push((c_string((*entry).pw_name), (*entry).pw_uid));
+        }
+        libc::endpwent();
+    }
+    entries.sort();
+    entries.dedup();
+    entries
+}
+
+/// Every group name the system lists, sorted.
+fn groups() -> Vec<(String, u32)> {
+    let mut entries = vec![];
+    unsafe {
+        libc::setgrent();
+        loop {
+            let entry = libc::getgrent();
+            if entry.is_null() {
+                break;
+            }
+            entries.push((c_string((*entry).gr_name), (*entry).gr_gid));
+        }
+        libc::endgrent();
+    }
+    entries.sort();
+    entries.dedup();
+    entries
+}
+
+unsafe fn c_string(ptr: *const libc::c_char) -> String {
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recursive_chmod_keeps_directories_searchable() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(sub.join("file"), "").unwrap();
+        let mode_of = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        assert_eq!(chmod(&sub, 0o640, true).unwrap(), 2);
+
+        assert_eq!(mode_of(&sub), 0o750);
+        assert_eq!(mode_of(&sub.join("file")), 0o640);
+    }
+
+    #[test]
+    fn parse_owner_reads_names_and_numbers() {
+        assert_eq!(parse_owner("root").unwrap(), (Some(0), None));
+        assert_eq!(parse_owner("0:0").unwrap(), (Some(0), Some(0)));
+        assert_eq!(parse_owner(":1234").unwrap(), (None, Some(1234)));
+        assert!(parse_owner("no-such-user-here").is_err());
+        assert!(parse_owner(":").is_err());
+    }
+
+    #[test]
+    fn recursive_chmod_changes_contents_before_their_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        let inner = sub.join("inner");
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(inner.join("file"), "").unwrap();
+        let mode_of = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        assert_eq!(
+            targets(&sub, true).unwrap(),
+            [inner.join("file"), inner.clone(), sub.clone()]
+        );
+        assert_eq!(chmod(&sub, 0o200, true).unwrap(), 3);
+
+        for path in [&sub, &inner] {
+            assert_eq!(mode_of(path), 0o200);
+            fs::set_permissions(path, fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        assert_eq!(mode_of(&inner.join("file")), 0o200);
+    }
+
+    #[test]
+    fn complete_owner_completes_users_then_groups() {
+        let complete = |spec| {
+            let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+            complete_owner_from(
+                spec,
+                || names(&["root", "rook", "daemon"]),
+                || names(&["wheel", "www", "staff"]),
+            )
+        };
+
+        assert_eq!(complete("d").as_deref(), Some("daemon"));
+        assert_eq!(complete("ro").as_deref(), Some("roo"));
+        assert_eq!(complete("root").as_deref(), Some("root"));
+        assert_eq!(complete("x"), None);
+        assert_eq!(complete("me:wh").as_deref(), Some("me:wheel"));
+        assert_eq!(complete("me:w").as_deref(), Some("me:w"));
+        assert_eq!(complete(":s").as_deref(), Some(":staff"));
+        assert_eq!(complete("me:x"), None);
+    }
+}
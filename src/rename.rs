@@ -0,0 +1,225 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// Checks that `name` can be used as a file name within a directory.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("the name is empty");
+    }
+    if name == "." || name == ".." {
+        bail!("'{}' is not a valid name", name);
+    }
+    if name.contains('/') || name.contains('\0') {
+        bail!("'{}': names cannot contain '/'", name);
+    }
+    Ok(())
+}
+
+/// Checks renaming each of `old_names` in `dir` to the corresponding entry of
+/// `new_names`: the lists must be the same length, the new names valid and
+/// distinct, and none may clobber an entry that is not itself being renamed.
+/// Returns the full paths of the renames that change something.
+pub fn plan(
+    dir: &Path,
+    old_names: &[String],
+    new_names: &[String],
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    if old_names.len() != new_names.len() {
+        bail!(
+            "expected {} names, got {}; lines must not be added or removed",
+            old_names.len(),
+            new_names.len()
+        );
+    }
+
+    let mut seen = HashSet::new();
+    for name in new_names {
+        validate_name(name)?;
+        if !seen.insert(name) {
+            bail!("'{}' is used more than once", name);
+        }
+    }
+
+    let sources: HashSet<&String> = old_names.iter().collect();
+    let mut renames = vec![];
+    for (old, new) in old_names.iter().zip(new_names) {
+        if old == new {
+            continue;
+        }
+        let target = dir.join(new);
+        if !sources.contains(new) && fs::symlink_metadata(&target).is_ok() {
+            bail!("{} already exists", target.display());
+        }
+        renames.push((dir.join(old), target));
+    }
+    Ok(renames)
+}
+
+/// Applies `renames` through temporary names, so chains and cycles such as
+/// swapping two names work. Whatever was done is undone when a step fails.
+pub fn apply(renames: &[(PathBuf, PathBuf)]) -> Result<()> {
+    let mut done: Vec<(PathBuf, PathBuf)> = vec![];
+    let result = apply_steps(renames, &mut done);
+    if result.is_err() {
+        for (from, to) in done.iter().rev() {
+            let _ = fs::rename(to, from);
+        }
+    }
+    result
+}
+
+fn apply_steps(renames: &[(PathBuf, PathBuf)], done: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    let mut staged = vec![];
+    for (i, (from, to)) in renames.iter().enumerate() {
+        let temp = temporary_name(from, i)?;
+        rename(from, &temp, done)?;
+        staged.push((temp, to));
+    }
+    for (temp, to) in staged {
+        if fs::symlink_metadata(to).is_ok() {
+            bail!("{} already exists", to.display());
+        }
+        rename(&temp, to, done)?;
+    }
+    Ok(())
+}
+
+fn rename(from: &Path, to: &Path, done: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    fs::rename(from, to)
+        .with_context(|| format!("failed to rename {} to {}", from.display(), to.display()))?;
+    done.push((from.to_path_buf(), to.to_path_buf()));
+    Ok(())
+}
+
+fn temporary_name(path: &Path, i: usize) -> Result<PathBuf> {
+    let temp = path.with_file_name(format!(".walker-rename-{}-{}", std::process::id(), i));
+    if fs::symlink_metadata(&temp).is_ok() {
+        bail!("{} already exists", temp.display());
+    }
+    Ok(temp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn touch(dir: &Path, names: &[&str]) {
+        for name in names {
+            fs::write(dir.join(name), name).unwrap();
+        }
+    }
+
+    fn contents(dir: &Path, name: &str) -> String {
+        fs::read_to_string(dir.join(name)).unwrap()
+    }
+
+    fn listing(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn plan_rejects_a_different_number_of_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = plan(dir.path(), &names(&["a", "b"]), &names(&["a"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected 2 names, got 1; lines must not be added or removed"
+        );
+    }
+
+    #[test]
+    fn plan_rejects_invalid_and_duplicate_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = names(&["a", "b"]);
+
+        let error = plan(dir.path(), &old, &names(&["c", "c"])).unwrap_err();
+        assert_eq!(error.to_string(), "'c' is used more than once");
+        let error = plan(dir.path(), &old, &names(&["c", "d/e"])).unwrap_err();
+        assert_eq!(error.to_string(), "'d/e': names cannot contain '/'");
+        let error = plan(dir.path(), &old, &names(&["", "b"])).unwrap_err();
+        assert_eq!(error.to_string(), "the name is empty");
+    }
+
+    #[test]
+    fn plan_rejects_clobbering_an_entry_not_being_renamed() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["a", "b"]);
+
+        let error = plan(dir.path(), &names(&["a"]), &names(&["b"])).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            format!("{} already exists", dir.path().join("b").display())
+        );
+    }
+
+    #[test]
+    fn plan_skips_unchanged_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let renames = plan(dir.path(), &names(&["a", "b"]), &names(&["a", "c"])).unwrap();
+        assert_eq!(renames, vec![(dir.path().join("b"), dir.path().join("c"))]);
+    }
+
+    #[test]
+    fn apply_swaps_two_names() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["a", "b"]);
+
+        let renames = plan(dir.path(), &names(&["a", "b"]), &names(&["b", "a"])).unwrap();
+        apply(&renames).unwrap();
+
+        assert_eq!(listing(dir.path()), ["a", "b"]);
+        assert_eq!(contents(dir.path(), "a"), "b");
+        assert_eq!(contents(dir.path(), "b"), "a");
+    }
+
+    #[test]
+    fn apply_rotates_a_cycle_of_three() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["a", "b", "c"]);
+
+        let old = names(&["a", "b", "c"]);
+        let renames = plan(dir.path(), &old, &names(&["b", "c", "a"])).unwrap();
+        apply(&renames).unwrap();
+
+        assert_eq!(listing(dir.path()), ["a", "b", "c"]);
+        assert_eq!(contents(dir.path(), "b"), "a");
+        assert_eq!(contents(dir.path(), "c"), "b");
+        assert_eq!(contents(dir.path(), "a"), "c");
+    }
+
+    #[test]
+    fn apply_restores_every_name_when_a_later_rename_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["a", "b"]);
+        let renames = plan(dir.path(), &names(&["a", "b"]), &names(&["x", "y"])).unwrap();
+        // Appears after planning, so only the second rename of the final
+        // phase finds its target taken.
+        touch(dir.path(), &["y"]);
+
+        let error = apply(&renames).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            format!("{} already exists", dir.path().join("y").display())
+        );
+        assert_eq!(listing(dir.path()), ["a", "b", "y"]);
+        assert_eq!(contents(dir.path(), "a"), "a");
+        assert_eq!(contents(dir.path(), "b"), "b");
+        assert_eq!(contents(dir.path(), "y"), "y");
+    }
+}
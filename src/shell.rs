@@ -0,0 +1,233 @@
+use std::{
+    os::unix::process::CommandExt,
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::{Context, Result};
+
+/// What the placeholders of a shell command stand for.
+#[derive(Clone, Debug, Default)]
+pub struct Placeholders {
+    /// `%s`: the selected entry.
+    pub selected: Option<String>,
+    /// `%f`: the marked entries, or the selected one when nothing is marked.
+    pub chosen: Vec<String>,
+    /// `%d`: the active panel's directory.
+    pub dir: String,
+    /// `%D`: the other panel's directory.
+    pub other_dir: String,
+}
+
+/// Replaces the placeholders in `command` with shell-quoted paths. `%%` is
+/// a literal `%`.
+pub fn expand(command: &str, placeholders: &Placeholders) -> String {
+    let mut expanded = String::new();
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => expanded.push_str(
+                &placeholders
+                    .selected
+                    .as_deref()
+                    .map(quote)
+                    .unwrap_or_default(),
+            ),
+            Some('f') => expanded.push_str(
+                &placeholders
+                    .chosen
+                    .iter()
+                    .map(|path| quote(path))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            Some('d') => expanded.push_str(&quote(&placeholders.dir)),
+            Some('D') => expanded.push_str(&quote(&placeholders.other_dir)),
+            Some('%') => expanded.push('%'),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+/// Quotes `s` for `sh`.
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// A command run in the background with no input, its output captured.
+#[derive(Debug)]
+pub struct Capture {
+    pub command: String,
+    /// The command's process group, so that whatever it started can be
+    /// killed along with it.
+    pgid: libc::pid_t,
+    output: Arc<Mutex<Option<Result<String>>>>,
+}
+
+impl Capture {
+    /// Starts `command` in `dir` in a process group of its own.
+    pub fn start(command: &str, dir: &str) -> Result<Self> {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .with_context(|| format!("failed to run {}", command))?;
+
+        let pgid = child.id() as libc::pid_t;
+        let output = Arc::new(Mutex::new(None));
+        let worker_output = Arc::clone(&output);
+        let worker_command = command.to_string();
+        thread::spawn(move || {
+            let text = child
+                .wait_with_output()
+                .map(output_text)
+                .with_context(|| format!("failed to run {}", worker_command));
+            if let Ok(mut output) = worker_output.lock() {
+                *output = Some(text);
+            }
+        });
+
+        Ok(Self {
+            command: command.to_string(),
+            pgid,
+            output,
+        })
+    }
+
+    /// What the command printed on stdout and stderr once it has exited,
+    /// noting a failing exit status at the end.
+    pub fn finished(&self) -> Option<Result<String>> {
+        self.output.lock().ok()?.take()
+    }
+
+    /// Kills the command and everything it started, unless it is done.
+    pub fn kill(&self) {
+        let done = self.output.lock().map_or(true, |output| output.is_some());
+        if !done {
+            unsafe { libc::kill(-self.pgid, libc::SIGKILL) };
+        }
+    }
+}
+
+fn output_text(output: Output) -> String {
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    if !output.status.success() {
+        text.push_str(&format!("\n[{}]", output.status));
+    }
+    text
+}
+
+/// Runs `command` in `dir` attached to the terminal.
+pub fn run_foreground(command: &str, dir: &str) -> Result<ExitStatus> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .status()
+        .with_context(|| format!("failed to run {}", command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NASTY: &[&str] = &[
+        "/tmp/plain",
+        "/tmp/with space",
+        "/tmp/it's",
+        "/tmp/$(touch pwned)",
+        "/tmp/`id`;rm -rf x",
+        "/tmp/new\nline",
+        "/tmp/back\\slash\"quote",
+    ];
+
+    fn placeholders() -> Placeholders {
+        Placeholders {
+            selected: Some("/tmp/it's".to_string()),
+            chosen: vec!["/tmp/a b".to_string(), "/tmp/c".to_string()],
+            dir: "/tmp".to_string(),
+            other_dir: "/srv/other dir".to_string(),
+        }
+    }
+
+    /// The words `sh` splits the expanded `command` into, as printed by it.
+    fn sh_words(command: &str, placeholders: &Placeholders) -> Vec<String> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(expand(command, placeholders))
+            .current_dir(std::env::temp_dir())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .split_terminator('\0')
+            .map(|word| word.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn expand_replaces_each_placeholder() {
+        assert_eq!(
+            expand("cp %f %D", &placeholders()),
+            "cp '/tmp/a b' '/tmp/c' '/srv/other dir'"
+        );
+        assert_eq!(
+            expand("cd %d && less %s", &placeholders()),
+            "cd '/tmp' && less '/tmp/it'\\''s'"
+        );
+        assert_eq!(expand("echo 100%% %x %", &placeholders()), "echo 100% %x %");
+    }
+
+    #[test]
+    fn expand_leaves_missing_entries_empty() {
+        let placeholders = Placeholders::default();
+        assert_eq!(expand("ls %s %f", &placeholders), "ls  ");
+        assert_eq!(expand("ls %d", &placeholders), "ls ''");
+    }
+
+    #[test]
+    fn quoted_names_reach_the_command_unchanged() {
+        for name in NASTY {
+            let placeholders = Placeholders {
+                selected: Some(name.to_string()),
+                ..Placeholders::default()
+            };
+            assert_eq!(sh_words("printf '%%s\\0' %s", &placeholders), [*name]);
+        }
+    }
+
+    #[test]
+    fn marked_names_stay_separate_words() {
+        let placeholders = Placeholders {
+            chosen: NASTY.iter().map(|name| name.to_string()).collect(),
+            dir: "/tmp/$HOME".to_string(),
+            other_dir: "/tmp/*".to_string(),
+            ..Placeholders::default()
+        };
+
+        let mut expected = NASTY.to_vec();
+        expected.extend(["/tmp/$HOME", "/tmp/*"]);
+        assert_eq!(
+            sh_words("printf '%%s\\0' %f %d %D", &placeholders),
+            expected
+        );
+    }
+}
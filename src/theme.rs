@@ -0,0 +1,545 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use tui::style::{Color, Modifier, Style};
+
+use crate::app::{Item, ItemKind};
+use crate::config::{Config, ThemeConfig};
+use crate::git::GitStatus;
+
+pub const DEFAULT_THEME: &str = "default";
+const BUILTIN_THEMES: [&str; 4] = [DEFAULT_THEME, "ocean", "light", "mono"];
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    pub header: Style,
+    pub active_tab: Style,
+    pub highlight: Style,
+    pub marked: Style,
+    pub border: Style,
+    pub file: Style,
+    pub directory: Style,
+    pub executable: Style,
+    pub symlink: Style,
+    pub broken_link: Style,
+    pub socket: Style,
+    pub fifo: Style,
+    pub device: Style,
+    pub git_modified: Style,
+    pub git_staged: Style,
+    pub git_untracked: Style,
+    pub git_ignored: Style,
+    pub git_conflicted: Style,
+    pub diff_added: Style,
+    pub diff_removed: Style,
+    pub diff_hunk: Style,
+    ls_colors: Option<LsColors>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_THEME.to_string(),
+            header: Style::default().fg(Color::Green),
+            active_tab: Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            highlight: Style::default()
+                .fg(Color::Rgb(0, 0, 0))
+                .bg(Color::Rgb(0, 125, 255))
+                .add_modifier(Modifier::BOLD),
+            marked: Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            border: Style::default(),
+            file: Style::default(),
+            directory: Style::default()
+                .fg(Color::LightBlue)
+                .add_modifier(Modifier::BOLD),
+            executable: Style::default().fg(Color::LightGreen),
+            symlink: Style::default().fg(Color::LightCyan),
+            broken_link: Style::default()
+                .fg(Color::LightRed)
+                .add_modifier(Modifier::CROSSED_OUT),
+            socket: Style::default().fg(Color::LightMagenta),
+            fifo: Style::default().fg(Color::Yellow),
+            device: Style::default()
+                .fg(Color::LightYellow)
+                .add_modifier(Modifier::BOLD),
+            git_modified: Style::default().fg(Color::Yellow),
+            git_staged: Style::default().fg(Color::LightGreen),
+            git_untracked: Style::default().fg(Color::LightRed),
+            git_ignored: Style::default().fg(Color::DarkGray),
+            git_conflicted: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            diff_added: Style::default().fg(Color::Green),
+            diff_removed: Style::default().fg(Color::Red),
+            diff_hunk: Style::default().fg(Color::Cyan),
+            ls_colors: None,
+        }
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Self> {
+        let default = Self::default();
+        let theme = match name {
+            DEFAULT_THEME => default,
+            "ocean" => Self {
+                header: Style::default().fg(Color::Cyan),
+                highlight: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+                border: Style::default().fg(Color::Blue),
+                directory: Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+                executable: Style::default().fg(Color::Green),
+                symlink: Style::default().fg(Color::LightBlue),
+                ..default
+            },
+            "light" => Self {
+                header: Style::default().fg(Color::Blue),
+                active_tab: Style::default()
+                    .fg(Color::Magenta)
+                    .add_modifier(Modifier::BOLD),
+                highlight: Style::default()
+                    .fg(Color::White)
+                    .bg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+                marked: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                file: Style::default().fg(Color::Black),
+                directory: Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+                executable: Style::default().fg(Color::Green),
+                symlink: Style::default().fg(Color::Magenta),
+                broken_link: Style::default().fg(Color::Red),
+                socket: Style::default().fg(Color::Magenta),
+                fifo: Style::default().fg(Color::DarkGray),
+                device: Style::default().fg(Color::DarkGray),
+                git_modified: Style::default().fg(Color::Magenta),
+                git_staged: Style::default().fg(Color::Green),
+                git_untracked: Style::default().fg(Color::Red),
+                git_ignored: Style::default().fg(Color::Gray),
+                diff_hunk: Style::default().fg(Color::Magenta),
+                ..default
+            },
+            "mono" => Self {
+                header: Style::default(),
+                active_tab: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                highlight: Style::default().add_modifier(Modifier::REVERSED),
+                marked: Style::default().add_modifier(Modifier::BOLD),
+                file: Style::default(),
+                directory: Style::default().add_modifier(Modifier::BOLD),
+                executable: Style::default(),
+                symlink: Style::default().add_modifier(Modifier::ITALIC),
+                broken_link: Style::default().add_modifier(Modifier::CROSSED_OUT),
+                socket: Style::default().add_modifier(Modifier::UNDERLINED),
+                fifo: Style::default().add_modifier(Modifier::UNDERLINED),
+                device: Style::default().add_modifier(Modifier::UNDERLINED),
+                git_modified: Style::default().add_modifier(Modifier::BOLD),
+                git_staged: Style::default(),
+                git_untracked: Style::default().add_modifier(Modifier::ITALIC),
+                git_ignored: Style::default().add_modifier(Modifier::DIM),
+                git_conflicted: Style::default().add_modifier(Modifier::REVERSED),
+                diff_added: Style::default().add_modifier(Modifier::BOLD),
+                diff_removed: Style::default().add_modifier(Modifier::DIM),
+                diff_hunk: Style::default().add_modifier(Modifier::ITALIC),
+                ..default
+            },
+            _ => return None,
+        };
+
+        Some(Self {
+            name: name.to_string(),
+            ..theme
+        })
+    }
+
+    /// Resolves `name` (or the configured theme when `None`) against the
+    /// user themes in `config` first and the built-in themes second.
+    pub fn resolve(config: &Config, name: Option<&str>) -> Result<Self> {
+        let name = name.or(config.theme.as_deref()).unwrap_or(DEFAULT_THEME);
+
+        let mut theme = match config.themes.get(name) {
+            Some(user) => Self::from_config(name, user)?,
+            None => Self::builtin(name).ok_or_else(|| anyhow!("unknown theme '{}'", name))?,
+        };
+
+        if config.ls_colors.unwrap_or(true) {
+            theme.ls_colors = LsColors::from_env();
+        }
+
+        Ok(theme)
+    }
+
+    /// Every theme `resolve` accepts: built-ins followed by user themes.
+    pub fn available(config: &Config) -> Vec<String> {
+        let mut user: Vec<_> = config
+            .themes
+            .keys()
+            .filter(|name| !BUILTIN_THEMES.contains(&name.as_str()))
+            .cloned()
+            .collect();
+        user.sort();
+
+        BUILTIN_THEMES
+            .iter()
+            .map(|name| name.to_string())
+            .chain(user)
+            .collect()
+    }
+
+    fn from_config(name: &str, user: &ThemeConfig) -> Result<Self> {
+        let base_name = user.base.as_deref().unwrap_or(DEFAULT_THEME);
+        let mut theme = Self::builtin(base_name)
+            .ok_or_else(|| anyhow!("theme '{}' has unknown base '{}'", name, base_name))?;
+        theme.name = name.to_string();
+
+        let overrides = [
+            (&user.header, &mut theme.header),
+            (&user.active_tab, &mut theme.active_tab),
+            (&user.highlight, &mut theme.highlight),
+            (&user.marked, &mut theme.marked),
+            (&user.border, &mut theme.border),
+            (&user.file, &mut theme.file),
+            (&user.directory, &mut theme.directory),
+            (&user.executable, &mut theme.executable),
+            (&user.symlink, &mut theme.symlink),
+            (&user.broken_link, &mut theme.broken_link),
+            (&user.socket, &mut theme.socket),
+            (&user.fifo, &mut theme.fifo),
+            (&user.device, &mut theme.device),
+            (&user.git_modified, &mut theme.git_modified),
+            (&user.git_staged, &mut theme.git_staged),
+            (&user.git_untracked, &mut theme.git_untracked),
+            (&user.git_ignored, &mut theme.git_ignored),
+            (&user.git_conflicted, &mut theme.git_conflicted),
+            (&user.diff_added, &mut theme.diff_added),
+            (&user.diff_removed, &mut theme.diff_removed),
+            (&user.diff_hunk, &mut theme.diff_hunk),
+        ];
+        for (spec, style) in overrides {
+            if let Some(spec) = spec {
+                *style = parse_style(spec)?;
+            }
+        }
+
+        Ok(theme)
+    }
+
+    /// The style an entry is drawn with. `LS_COLORS` wins over the theme
+    /// whenever it has an entry for the item.
+    pub fn style_for(&self, item: &Item) -> Style {
+        self.ls_colors
+            .as_ref()
+            .and_then(|ls| ls.style_for(item))
+            .unwrap_or_else(|| self.kind_style(item))
+    }
+
+    pub fn git_style(&self, status: GitStatus) -> Style {
+        match status {
+            GitStatus::Modified => self.git_modified,
+            GitStatus::Staged => self.git_staged,
+            GitStatus::Untracked => self.git_untracked,
+            GitStatus::Ignored => self.git_ignored,
+            GitStatus::Conflicted => self.git_conflicted,
+        }
+    }
+
+    fn kind_style(&self, item: &Item) -> Style {
+        match item.kind {
+            ItemKind::Directory => self.directory,
+            ItemKind::Symlink => self.symlink,
+            ItemKind::BrokenSymlink => self.broken_link,
+            ItemKind::Socket => self.socket,
+            ItemKind::Fifo => self.fifo,
+            ItemKind::BlockDevice | ItemKind::CharDevice => self.device,
+            ItemKind::File if item.is_executable() => self.executable,
+            ItemKind::File => self.file,
+        }
+    }
+}
+
+/// Parses a style spec: whitespace separated modifiers and colours, where a
+/// colour following `on` is the background, e.g. `"bold black on #007dff"`.
+pub fn parse_style(spec: &str) -> Result<Style> {
+    let mut style = Style::default();
+    let mut background = false;
+    for token in spec.split_whitespace() {
+        let token = token.to_lowercase();
+        if token == "on" {
+            background = true;
+            continue;
+        }
+
+        if let Some(modifier) = parse_modifier(&token) {
+            style = style.add_modifier(modifier);
+            continue;
+        }
+
+        let color = parse_color(&token)
+            .ok_or_else(|| anyhow!("invalid style '{}': unknown token '{}'", spec, token))?;
+        style = if background {
+            style.bg(color)
+        } else {
+            style.fg(color)
+        };
+        background = false;
+    }
+
+    if background {
+        bail!("invalid style '{}': missing colour after 'on'", spec);
+    }
+
+    Ok(style)
+}
+
+fn parse_modifier(token: &str) -> Option<Modifier> {
+    let modifier = match token {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underline" | "underlined" => Modifier::UNDERLINED,
+        "blink" => Modifier::SLOW_BLINK,
+        "reversed" | "reverse" => Modifier::REVERSED,
+        "hidden" => Modifier::HIDDEN,
+        "crossed_out" | "strikethrough" => Modifier::CROSSED_OUT,
+        _ => return None,
+    };
+    Some(modifier)
+}
+
+/// Accepts colour names, `#rrggbb` and 256-colour palette indices.
+pub fn parse_color(token: &str) -> Option<Color> {
+    if let Some(hex) = token.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+
+    if let Ok(index) = token.parse::<u8>() {
+        return Some(Color::Indexed(index));
+    }
+
+    let color = match token.replace(&['-', '_'][..], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// The subset of `LS_COLORS` walker understands: the file type keys and
+/// `*.ext` extension patterns.
+#[derive(Clone, Debug, Default)]
+pub struct LsColors {
+    kinds: HashMap<String, Style>,
+    extensions: HashMap<String, Style>,
+}
+
+impl LsColors {
+    pub fn from_env() -> Option<Self> {
+        std::env::var("LS_COLORS")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(|v| Self::parse(&v))
+    }
+
+    pub fn parse(value: &str) -> Self {
+        let mut ls_colors = Self::default();
+        for entry in value.split(':') {
+            let (key, codes) = match entry.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            // `ln=target` means "colour as the link target", which is what
+            // walker does anyway when there is no entry.
+            let style = match parse_sgr(codes) {
+                Some(style) => style,
+                None => continue,
+            };
+
+            if let Some(ext) = key.strip_prefix("*.") {
+                ls_colors.extensions.insert(ext.to_lowercase(), style);
+            } else if !key.starts_with('*') {
+                ls_colors.kinds.insert(key.to_string(), style);
+            }
+        }
+        ls_colors
+    }
+
+    fn style_for(&self, item: &Item) -> Option<Style> {
+        let kind = |key: &str| self.kinds.get(key).copied();
+        match item.kind {
+            ItemKind::Directory => kind("di"),
+            ItemKind::Symlink => kind("ln"),
+            ItemKind::BrokenSymlink => kind("or").or_else(|| kind("ln")),
+            ItemKind::Socket => kind("so"),
+            ItemKind::Fifo => kind("pi"),
+            ItemKind::BlockDevice => kind("bd"),
+            ItemKind::CharDevice => kind("cd"),
+            ItemKind::File => {
+                if item.is_executable() {
+                    if let Some(style) = kind("ex") {
+                        return Some(style);
+                    }
+                }
+                Path::new(&item.name)
+                    .extension()
+                    .and_then(|ext| {
+                        self.extensions
+                            .get(&ext.to_string_lossy().to_lowercase())
+                            .copied()
+                    })
+                    .or_else(|| kind("fi"))
+            }
+        }
+    }
+}
+
+/// Converts a `;` separated list of SGR codes into a style.
+fn parse_sgr(codes: &str) -> Option<Style> {
+    let codes: Vec<u8> = codes
+        .split(';')
+        .map(|c| c.parse::<u8>())
+        .collect::<std::result::Result<_, _>>()
+        .ok()?;
+
+    let mut style = Style::default();
+    let mut iter = codes.into_iter();
+    while let Some(code) = iter.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            5 => style.add_modifier(Modifier::SLOW_BLINK),
+            6 => style.add_modifier(Modifier::RAPID_BLINK),
+            7 => style.add_modifier(Modifier::REVERSED),
+            8 => style.add_modifier(Modifier::HIDDEN),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(ansi_color(code - 30, false)),
+            38 => style.fg(extended_color(&mut iter)?),
+            39 => style.fg(Color::Reset),
+            40..=47 => style.bg(ansi_color(code - 40, false)),
+            48 => style.bg(extended_color(&mut iter)?),
+            49 => style.bg(Color::Reset),
+            90..=97 => style.fg(ansi_color(code - 90, true)),
+            100..=107 => style.bg(ansi_color(code - 100, true)),
+            _ => style,
+        };
+    }
+
+    Some(style)
+}
+
+fn extended_color(iter: &mut impl Iterator<Item = u8>) -> Option<Color> {
+    match iter.next()? {
+        5 => Some(Color::Indexed(iter.next()?)),
+        2 => Some(Color::Rgb(iter.next()?, iter.next()?, iter.next()?)),
+        _ => None,
+    }
+}
+
+fn ansi_color(index: u8, bright: bool) -> Color {
+    match (index, bright) {
+        (0, false) => Color::Black,
+        (1, false) => Color::Red,
+        (2, false) => Color::Green,
+        (3, false) => Color::Yellow,
+        (4, false) => Color::Blue,
+        (5, false) => Color::Magenta,
+        (6, false) => Color::Cyan,
+        (7, false) => Color::Gray,
+        (0, true) => Color::DarkGray,
+        (1, true) => Color::LightRed,
+        (2, true) => Color::LightGreen,
+        (3, true) => Color::LightYellow,
+        (4, true) => Color::LightBlue,
+        (5, true) => Color::LightMagenta,
+        (6, true) => Color::LightCyan,
+        (7, true) => Color::White,
+        _ => Color::Reset,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_color_reads_hex_names_and_indices() {
+        assert_eq!(parse_color("#00ff7f"), Some(Color::Rgb(0, 255, 127)));
+        assert_eq!(parse_color("light-blue"), Some(Color::LightBlue));
+        assert_eq!(parse_color("208"), Some(Color::Indexed(208)));
+    }
+
+    #[test]
+    fn parse_color_rejects_bad_hex_without_panicking() {
+        assert_eq!(parse_color("#a\u{e9}bcd"), None);
+        assert_eq!(parse_color("#abcde"), None);
+        assert_eq!(parse_color("#gggggg"), None);
+    }
+
+    #[test]
+    fn a_broken_user_theme_only_fails_when_picked() {
+        let mut config = Config::default();
+        config.themes.insert(
+            "broken".to_string(),
+            ThemeConfig {
+                base: Some("nonexistent".to_string()),
+                ..ThemeConfig::default()
+            },
+        );
+
+        assert!(Theme::resolve(&config, None).is_ok());
+        assert!(Theme::resolve(&config, Some("broken")).is_err());
+    }
+
+    #[test]
+    fn user_themes_override_diff_styles() {
+        let mut config = Config::default();
+        config.themes.insert(
+            "mine".to_string(),
+            ThemeConfig {
+                base: Some("mono".to_string()),
+                diff_added: Some("bold blue".to_string()),
+                ..ThemeConfig::default()
+            },
+        );
+
+        let theme = Theme::resolve(&config, Some("mine")).unwrap();
+
+        assert_eq!(
+            theme.diff_added,
+            Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(
+            theme.diff_removed,
+            Style::default().add_modifier(Modifier::DIM)
+        );
+    }
+}
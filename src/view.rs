@@ -1,33 +1,72 @@
 use anyhow::Result;
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tui::widgets::TableState;
 use tui_input::Input;
 
-use crate::app::{get_contents, EditingKind, InputMode, Item};
+use crate::app::{EditingKind, InputMode, Item, ItemKind};
+use crate::du::DirSizes;
+use crate::git::RepoStatus;
+use crate::listing::{self, ListOptions, SortKind};
+use crate::rename;
+
+const MAX_HISTORY: usize = 100;
+
+/// The selected entry in a directory and the table state, which carries the
+/// scroll offset.
+#[derive(Clone, Debug, Default)]
+struct Cursor {
+    selected: Option<String>,
+    table_state: TableState,
+}
+
+#[derive(Clone, Debug)]
+struct Visit {
+    dir: String,
+    cursor: Cursor,
+}
 
 #[derive(Clone, Debug)]
 pub struct WalkerState {
     current_dir: String,
+    /// `current_dir` with symlinks resolved, worked out once per change.
+    physical_dir: String,
     directory_table_state: TableState,
     current_contents: Vec<Item>,
-    is_editing: bool,
     file_to_edit: Item,
-    editing_index: usize,
     input_mode: InputMode,
     text_input: Input,
+    back: Vec<Visit>,
+    forward: Vec<Visit>,
+    cursors: HashMap<String, Cursor>,
+    marked: HashSet<String>,
+    list_options: ListOptions,
+    disk_usage: bool,
+    /// The repository the current directory is in, once `git status` ran.
+    git: Option<Arc<RepoStatus>>,
 }
 
 impl Default for WalkerState {
     fn default() -> Self {
         Self {
             current_dir: String::new(),
+            physical_dir: String::new(),
             directory_table_state: TableState::default(),
             current_contents: vec![],
-            is_editing: false,
             file_to_edit: Item::default(),
-            editing_index: 0,
             input_mode: InputMode::Normal,
             text_input: Input::default(),
+            back: vec![],
+            forward: vec![],
+            cursors: HashMap::new(),
+            marked: HashSet::new(),
+            list_options: ListOptions::default(),
+            disk_usage: false,
+            git: None,
         }
     }
 }
@@ -46,10 +85,6 @@ impl Default for WalkerView {
 }
 
 impl WalkerView {
-    pub fn new() -> Self {
-        Self::default()
-    }
-
     pub fn set_current_dir(&mut self, dir: &str) {
         if self.state.current_dir != dir {
             let path = Path::new(dir);
@@ -61,7 +96,118 @@ impl WalkerView {
                     .map_or(String::new(), |p| p.display().to_string());
                 self.state.current_dir = parent_dir.to_string();
             }
-            self.load_dir();
+            self.state.physical_dir = fs::canonicalize(&self.state.current_dir)
+                .map_or(self.state.current_dir.clone(), |path| {
+                    path.display().to_string()
+                });
+            self.state.marked.clear();
+            self.state.git = None;
+            let _ = self.load_dir();
+        }
+    }
+
+    /// Navigates to `dir`, remembering the directory being left for
+    /// `go_back` and restoring the cursor `dir` had when we last left it.
+    pub fn change_dir(&mut self, dir: &str) {
+        let visit = self.visit();
+        self.set_current_dir(dir);
+        if visit.dir == self.state.current_dir {
+            return;
+        }
+
+        if !visit.dir.is_empty() {
+            self.remember_cursor(&visit);
+            self.state.back.push(visit);
+            if self.state.back.len() > MAX_HISTORY {
+                self.state.back.remove(0);
+            }
+            self.state.forward.clear();
+        }
+
+        let cursor = self
+            .state
+            .cursors
+            .get(&self.state.current_dir)
+            .cloned()
+            .unwrap_or_default();
+        self.restore_cursor(cursor);
+    }
+
+    pub fn go_back(&mut self) {
+        if let Some(visit) = Self::pop_existing(&mut self.state.back) {
+            let current = self.visit();
+            self.remember_cursor(&current);
+            self.state.forward.push(current);
+            self.restore_visit(visit);
+        }
+    }
+
+    pub fn go_forward(&mut self) {
+        if let Some(visit) = Self::pop_existing(&mut self.state.forward) {
+            let current = self.visit();
+            self.remember_cursor(&current);
+            self.state.back.push(current);
+            self.restore_visit(visit);
+        }
+    }
+
+    /// Pops the newest visit whose directory still exists.
+    fn pop_existing(history: &mut Vec<Visit>) -> Option<Visit> {
+        while let Some(visit) = history.pop() {
+            if Path::new(&visit.dir).is_dir() {
+                return Some(visit);
+            }
+        }
+        None
+    }
+
+    fn visit(&self) -> Visit {
+        Visit {
+            dir: self.state.current_dir.clone(),
+            cursor: Cursor {
+                selected: self.selected_item().map(|item| item.name.clone()),
+                table_state: self.state.directory_table_state.clone(),
+            },
+        }
+    }
+
+    fn remember_cursor(&mut self, visit: &Visit) {
+        self.state
+            .cursors
+            .insert(visit.dir.clone(), visit.cursor.clone());
+    }
+
+    fn restore_visit(&mut self, visit: Visit) {
+        self.set_current_dir(&visit.dir);
+        self.restore_cursor(visit.cursor);
+    }
+
+    /// Selects the remembered entry, falling back to the remembered row
+    /// (or the first one) when it has gone away.
+    fn restore_cursor(&mut self, cursor: Cursor) {
+        self.state.directory_table_state = cursor.table_state;
+        let restored = cursor
+            .selected
+            .is_some_and(|name| self.select_by_name(&name));
+        if !restored && self.state.directory_table_state.selected().is_none() {
+            self.state.directory_table_state.select(Some(0));
+        }
+        self.clamp_selection();
+    }
+
+    /// Selects the entry called `name`, returning whether there was one.
+    pub fn select_by_name(&mut self, name: &str) -> bool {
+        match self
+            .state
+            .current_contents
+            .iter()
+            .position(|item| item.name == name)
+        {
+            Some(idx) => {
+                self.state.directory_table_state.select(Some(idx));
+                true
+            }
+            None => false,
         }
     }
 
@@ -69,33 +215,153 @@ impl WalkerView {
         &self.state.current_dir
     }
 
+    /// The current directory as it is shown: as navigated, or with symlinks
+    /// resolved when `physical`, like `pwd -L` and `pwd -P`.
+    pub fn display_dir(&self, physical: bool) -> &str {
+        if physical {
+            &self.state.physical_dir
+        } else {
+            &self.state.current_dir
+        }
+    }
+
     pub fn current_contents(&self) -> &[Item] {
         &self.state.current_contents
     }
 
+    /// Re-reads the current directory, keeping the selected entry selected
+    /// when it still exists. Directory sizes are left pending until
+    /// `apply_dir_sizes` fills in their recursive totals, once cached.
     pub fn load_dir(&mut self) -> Result<()> {
-        self.state.current_contents = get_contents(&self.state.current_dir)?;
+        let selected = self.selected_item().map(|item| item.name.clone());
+        self.state.current_contents =
+            listing::list_dir(&self.state.current_dir, &self.state.list_options)?;
+        for item in self.state.current_contents.iter_mut() {
+            if item.kind == ItemKind::Directory {
+                item.size = 0;
+                item.size_pending = true;
+            }
+        }
+        self.annotate_git_status();
+        if self.state.disk_usage {
+            self.sort_by_disk_usage();
+        }
+        if !selected.is_some_and(|name| self.select_by_name(&name)) {
+            self.clamp_selection();
+        }
         Ok(())
     }
 
-    pub fn state(&self) -> &WalkerState {
-        &self.state
+    pub fn list_options(&self) -> &ListOptions {
+        &self.state.list_options
     }
 
-    pub fn state_mut(&mut self) -> &mut WalkerState {
-        &mut self.state
+    /// Changes how the listing is sorted and filtered and reloads it.
+    pub fn set_list_options(&mut self, options: ListOptions) -> Result<()> {
+        self.state.list_options = options;
+        self.load_dir()
     }
 
-    pub fn directory_table_state(&self) -> &TableState {
-        &self.state.directory_table_state
+    pub fn is_disk_usage(&self) -> bool {
+        self.state.disk_usage
     }
 
-    pub fn directory_table_state_mut(&mut self) -> &mut TableState {
-        &mut self.state.directory_table_state
+    /// Switches between the normal listing and the disk usage one, where the
+    /// biggest entries come first.
+    pub fn set_disk_usage(&mut self, disk_usage: bool) -> Result<()> {
+        self.state.disk_usage = disk_usage;
+        self.load_dir()
     }
 
-    pub fn set_directory_table_state(&mut self, state: TableState) {
-        self.state.directory_table_state = state;
+    /// Directories in the listing waiting for a total size that is wanted:
+    /// all of them in the disk usage view, otherwise only the selected one,
+    /// so that opening a big tree does not scan all of it.
+    pub fn pending_dir_sizes(&self) -> impl Iterator<Item = &String> {
+        let selected = self.selected_item().map(|item| &item.name);
+        let all = self.state.disk_usage;
+        self.state
+            .current_contents
+            .iter()
+            .filter(move |item| item.size_pending && (all || Some(&item.name) == selected))
+            .map(|item| &item.name)
+    }
+
+    /// Fills in the directory sizes `sizes` has computed so far, re-sorting
+    /// when the listing is ordered by size.
+    pub fn apply_dir_sizes(&mut self, sizes: &DirSizes) {
+        let mut changed = false;
+        for item in self.state.current_contents.iter_mut() {
+            if item.size_pending {
+                if let Some(size) = sizes.get(&item.name) {
+                    item.size = size;
+                    item.size_pending = false;
+                    changed = true;
+                }
+            }
+        }
+
+        if changed && (self.state.disk_usage || self.state.list_options.sort == SortKind::Size) {
+            let selected = self.selected_item().map(|item| item.name.clone());
+            if self.state.disk_usage {
+                self.sort_by_disk_usage();
+            } else {
+                listing::sort_items(&mut self.state.current_contents, &self.state.list_options);
+            }
+            if let Some(name) = selected {
+                self.select_by_name(&name);
+            }
+        }
+    }
+
+    /// Switches to the status of `repo`, unless it is the one already shown.
+    pub fn apply_git_status(&mut self, repo: Option<Arc<RepoStatus>>) {
+        let unchanged = match (&self.state.git, &repo) {
+            (Some(shown), Some(repo)) => Arc::ptr_eq(shown, repo),
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            self.state.git = repo;
+            self.annotate_git_status();
+        }
+    }
+
+    fn annotate_git_status(&mut self) {
+        for item in self.state.current_contents.iter_mut() {
+            item.git_status = self
+                .state
+                .git
+                .as_ref()
+                .and_then(|repo| repo.status_of(Path::new(&item.name)));
+        }
+    }
+
+    /// The repository the current directory is in, if any.
+    pub fn git_repo(&self) -> Option<&RepoStatus> {
+        self.state.git.as_deref()
+    }
+
+    /// The size of everything listed, directories counted recursively.
+    pub fn total_size(&self) -> u64 {
+        self.state
+            .current_contents
+            .iter()
+            .map(|item| item.size)
+            .sum()
+    }
+
+    fn sort_by_disk_usage(&mut self) {
+        let options = ListOptions {
+            sort: SortKind::Size,
+            reverse: true,
+            dirs_first: false,
+            ..self.state.list_options.clone()
+        };
+        listing::sort_items(&mut self.state.current_contents, &options);
+    }
+
+    pub fn directory_table_state_mut(&mut self) -> &mut TableState {
+        &mut self.state.directory_table_state
     }
 
     pub fn text_input(&self) -> &Input {
@@ -106,15 +372,15 @@ impl WalkerView {
         &mut self.state.text_input
     }
 
-    pub fn is_editing(&self) -> bool {
-        self.state.is_editing
-    }
-
     pub fn input_mode(&self) -> InputMode {
         self.state.input_mode
     }
 
     pub fn move_selection_up(&mut self) {
+        if self.state.current_contents.is_empty() {
+            return;
+        }
+
         if let Some(selected) = self.state.directory_table_state.selected() {
             if selected > 0 {
                 self.state.directory_table_state.select(Some(selected - 1));
@@ -127,6 +393,10 @@ impl WalkerView {
     }
 
     pub fn move_selection_down(&mut self) {
+        if self.state.current_contents.is_empty() {
+            return;
+        }
+
         if let Some(selected) = self.state.directory_table_state.selected() {
             if selected >= self.state.current_contents.len() - 1 {
                 self.state.directory_table_state.select(Some(0));
@@ -137,21 +407,55 @@ impl WalkerView {
     }
 
     pub fn move_into_child_dir(&mut self) {
-        if let Some(idx) = self.state.directory_table_state.selected() {
-            if let Some(item) = self.state.current_contents.get(idx) {
-                let full_path = Path::new(&self.state.current_dir).join(&item.name);
-                self.set_current_dir(&full_path.display().to_string());
-                self.state.directory_table_state.select(Some(0));
-            }
+        if let Some(item) = self.selected_item().filter(|item| item.is_dir) {
+            let full_path = Path::new(&self.state.current_dir).join(&item.name);
+            self.change_dir(&full_path.display().to_string());
         }
     }
 
+    /// Goes up a level with the directory we came from selected.
     pub fn move_upto_parent_dir(&mut self) {
-        if let Some(idx) = self.state.directory_table_state.selected() {
-            if let Some(parent) = Path::new(&self.state.current_dir.clone()).parent() {
-                self.set_current_dir(&parent.display().to_string());
-                self.state.directory_table_state.select(Some(0));
+        let child = self.state.current_dir.clone();
+        if let Some(parent) = Path::new(&child).parent() {
+            self.change_dir(&parent.display().to_string());
+            self.select_by_name(&child);
+        }
+    }
+
+    /// Marks or unmarks the selected entry and moves on to the next one.
+    pub fn toggle_mark(&mut self) {
+        if let Some(name) = self.selected_item().map(|item| item.name.clone()) {
+            if !self.state.marked.remove(&name) {
+                self.state.marked.insert(name);
             }
+            self.move_selection_down();
+        }
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.state.marked.clear();
+    }
+
+    pub fn is_marked(&self, item: &Item) -> bool {
+        self.state.marked.contains(&item.name)
+    }
+
+    /// Marked entries still in the listing, in listing order.
+    pub fn marked_items(&self) -> Vec<&Item> {
+        self.state
+            .current_contents
+            .iter()
+            .filter(|item| self.is_marked(item))
+            .collect()
+    }
+
+    /// The marked entries, or the selected one when nothing is marked.
+    pub fn marked_or_selected(&self) -> Vec<&Item> {
+        let marked = self.marked_items();
+        if marked.is_empty() {
+            self.selected_item().into_iter().collect()
+        } else {
+            marked
         }
     }
 
@@ -165,17 +469,19 @@ impl WalkerView {
         None
     }
 
+    /// Starts renaming the selected entry, editing just its file name with
+    /// the cursor before the extension.
     pub fn start_rename_file(&mut self) {
-        self.state.is_editing = true;
-        if let Some(selected_item) = self.selected_item() {
-            let path = Path::new(&selected_item.name);
-            self.state.file_to_edit = selected_item.clone();
+        if let Some(selected_item) = self.selected_item().cloned() {
+            let name = listing::file_name(&selected_item).to_string();
+            let cursor = match Path::new(&name).file_stem() {
+                Some(stem) if !selected_item.is_dir => stem.to_string_lossy().chars().count(),
+                _ => name.chars().count(),
+            };
+
+            self.state.file_to_edit = selected_item;
             self.state.input_mode = InputMode::Editing(EditingKind::Rename);
-            self.state.text_input = self
-                .state
-                .text_input
-                .clone()
-                .with_value(self.state.file_to_edit.name.clone());
+            self.state.text_input = Input::default().with_value(name).with_cursor(cursor);
         }
     }
 
@@ -184,28 +490,52 @@ impl WalkerView {
             InputMode::Normal => {
                 self.state.file_to_edit = Item::default();
                 self.state.input_mode = input_mode;
-                self.state.is_editing = false;
             }
             InputMode::Editing(_) => {}
         }
     }
 
-    pub fn rename_file(&mut self) {
-        let name: String = self.state.text_input.value().into();
-        std::fs::rename(&self.state.file_to_edit.name, &name);
+    /// Renames the entry being edited to the name typed, within the current
+    /// directory, and selects it. Returns the new path.
+    pub fn rename_file(&mut self) -> Result<PathBuf> {
+        let old_name = listing::file_name(&self.state.file_to_edit).to_string();
+        let new_name = self.state.text_input.value().to_string();
         self.set_input_mode(InputMode::Normal);
-        self.state.directory_table_state.select(Some(0));
-        self.load_dir();
+
+        let dir = Path::new(&self.state.current_dir);
+        let renames = rename::plan(dir, &[old_name], std::slice::from_ref(&new_name))?;
+        rename::apply(&renames)?;
+
+        let new_path = dir.join(&new_name);
+        self.load_dir()?;
+        self.select_by_name(&new_path.display().to_string());
+        Ok(new_path)
     }
 
-    pub fn initiate_file_copy(&mut self) {
-        self.state.is_editing = true;
-        if let Some(idx) = self.state.directory_table_state.selected() {
-            if let Some(selected_item) = self.state.current_contents.get(idx) {
-                let path = Path::new(&selected_item.name);
-                self.state.file_to_edit = selected_item.clone();
-                self.state.input_mode = InputMode::Editing(EditingKind::Copy);
-            }
+    pub fn initiate_transfer(&mut self, kind: EditingKind, destination: &str) {
+        if let Some(selected_item) = self.selected_item().cloned() {
+            self.state.file_to_edit = selected_item;
+            self.state.input_mode = InputMode::Editing(kind);
+            self.state.text_input = Input::default().with_value(destination.to_string());
         }
     }
+
+    /// Starts editing `value` in the text input without an item to act on.
+    pub fn start_input(&mut self, kind: EditingKind, value: &str) {
+        self.state.input_mode = InputMode::Editing(kind);
+        self.state.text_input = Input::default().with_value(value.to_string());
+    }
+
+    pub fn file_to_edit(&self) -> &Item {
+        &self.state.file_to_edit
+    }
+
+    /// Keeps the selection inside the listing after it shrank.
+    pub fn clamp_selection(&mut self) {
+        let len = self.state.current_contents.len();
+        let selected = self.state.directory_table_state.selected().unwrap_or(0);
+        self.state
+            .directory_table_state
+            .select(Some(selected.min(len.saturating_sub(1))));
+    }
 }
@@ -0,0 +1,253 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fs::File,
+    io::{self, Read},
+    mem,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd},
+    },
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+/// How long a directory has to be quiet before it is reported, so a burst of
+/// changes such as a build only reloads it once.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+/// A directory that keeps changing is still reported this often.
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+const EVENTS: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_ATTRIB
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF
+    | libc::IN_ONLYDIR;
+
+/// When a directory first and last changed since it was reported.
+#[derive(Debug)]
+struct Change {
+    first: Instant,
+    last: Instant,
+}
+
+/// Watches directories with inotify and reports the ones whose entries
+/// changed, once the changes settle down.
+#[derive(Debug)]
+pub struct Watcher {
+    inotify: File,
+    dirs: HashMap<String, i32>,
+    /// The directories behind each watch; inotify hands out the same one
+    /// for every path to a directory, e.g. a symlink and its target.
+    watches: HashMap<i32, Vec<String>>,
+    /// Directories that could not be watched, e.g. because they were
+    /// deleted, which are only tried again once they exist.
+    missing: HashSet<String>,
+    changes: HashMap<String, Change>,
+}
+
+impl Watcher {
+    pub fn new() -> Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("failed to start watching");
+        }
+
+        Ok(Self {
+            inotify: unsafe { File::from_raw_fd(fd) },
+            dirs: HashMap::new(),
+            watches: HashMap::new(),
+            missing: HashSet::new(),
+            changes: HashMap::new(),
+        })
+    }
+
+    /// Watches exactly `dirs`, dropping the watches of directories no longer
+    /// in it. Directories that cannot be watched are skipped until they exist
+    /// again, and then reported as changed.
+    pub fn watch(&mut self, dirs: HashSet<String>) {
+        let stale: Vec<String> = self
+            .dirs
+            .keys()
+            .filter(|dir| !dirs.contains(*dir))
+            .cloned()
+            .collect();
+        for dir in stale {
+            if let Some(wd) = self.dirs.remove(&dir) {
+                self.unwatch(wd, &dir);
+            }
+            self.changes.remove(&dir);
+        }
+        self.missing.retain(|dir| dirs.contains(dir));
+
+        for dir in dirs {
+            if dir.is_empty() || self.dirs.contains_key(&dir) {
+                continue;
+            }
+            if self.missing.contains(&dir) && !Path::new(&dir).is_dir() {
+                continue;
+            }
+            let path = match CString::new(Path::new(&dir).as_os_str().as_bytes()) {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            let wd =
+                unsafe { libc::inotify_add_watch(self.inotify.as_raw_fd(), path.as_ptr(), EVENTS) };
+            if wd < 0 {
+                self.missing.insert(dir);
+                continue;
+            }
+            if self.missing.remove(&dir) {
+                self.record_change(dir.clone());
+            }
+            self.watches.entry(wd).or_default().push(dir.clone());
+            self.dirs.insert(dir, wd);
+        }
+    }
+
+    /// Stops watching `dir`, and drops the watch once no other path uses it.
+    fn unwatch(&mut self, wd: i32, dir: &str) {
+        if let Some(dirs) = self.watches.get_mut(&wd) {
+            dirs.retain(|d| d != dir);
+            if dirs.is_empty() {
+                self.watches.remove(&wd);
+                unsafe { libc::inotify_rm_watch(self.inotify.as_raw_fd(), wd) };
+            }
+        }
+    }
+
+    /// The watched directories that changed and have since settled.
+    pub fn changed_dirs(&mut self) -> Vec<String> {
+        self.read_events();
+
+        let now = Instant::now();
+        let settled: Vec<String> = self
+            .changes
+            .iter()
+            .filter(|(_, c)| now - c.last >= DEBOUNCE || now - c.first >= MAX_DELAY)
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        for dir in &settled {
+            self.changes.remove(dir);
+        }
+        settled
+    }
+
+    fn read_events(&mut self) {
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = match self.inotify.read(&mut buf) {
+                Ok(len) if len > 0 => len,
+                _ => return,
+            };
+
+            let mut offset = 0;
+            while offset + mem::size_of::<libc::inotify_event>() <= len {
+                let event = unsafe {
+                    (buf.as_ptr().add(offset) as *const libc::inotify_event).read_unaligned()
+                };
+                offset += mem::size_of::<libc::inotify_event>() + event.len as usize;
+
+                let dirs = match self.watches.get(&event.wd) {
+                    Some(dirs) => dirs.clone(),
+                    None => continue,
+                };
+                if event.mask & libc::IN_IGNORED != 0 {
+                    self.watches.remove(&event.wd);
+                    for dir in &dirs {
+                        self.dirs.remove(dir);
+                        self.missing.insert(dir.clone());
+                    }
+                }
+
+                for dir in dirs {
+                    self.record_change(dir);
+                }
+            }
+        }
+    }
+
+    fn record_change(&mut self, dir: String) {
+        let now = Instant::now();
+        self.changes
+            .entry(dir)
+            .and_modify(|c| c.last = now)
+            .or_insert(Change {
+                first: now,
+                last: now,
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink, thread};
+
+    use super::*;
+
+    /// Reads the pending events, then waits for them to settle.
+    fn settled_changes(watcher: &mut Watcher) -> Vec<String> {
+        assert!(watcher.changed_dirs().is_empty());
+        thread::sleep(DEBOUNCE + Duration::from_millis(50));
+        let mut dirs = watcher.changed_dirs();
+        dirs.sort();
+        dirs
+    }
+
+    fn set(dirs: &[&String]) -> HashSet<String> {
+        dirs.iter().map(|dir| dir.to_string()).collect()
+    }
+
+    #[test]
+    fn a_directory_and_a_link_to_it_share_a_watch() {
+        let root = tempfile::tempdir().unwrap();
+        let target = root.path().join("target");
+        let link = root.path().join("link");
+        fs::create_dir(&target).unwrap();
+        symlink(&target, &link).unwrap();
+        let (target, link) = (target.display().to_string(), link.display().to_string());
+        let mut watcher = Watcher::new().unwrap();
+
+        watcher.watch(set(&[&target, &link]));
+        fs::write(Path::new(&target).join("a"), "").unwrap();
+        assert_eq!(
+            settled_changes(&mut watcher),
+            [link.clone(), target.clone()]
+        );
+
+        watcher.watch(set(&[&target]));
+        fs::write(Path::new(&target).join("b"), "").unwrap();
+        assert_eq!(settled_changes(&mut watcher), [target]);
+    }
+
+    #[test]
+    fn a_deleted_directory_is_watched_again_once_recreated() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        let name = dir.display().to_string();
+        let mut watcher = Watcher::new().unwrap();
+
+        watcher.watch(set(&[&name]));
+        fs::remove_dir(&dir).unwrap();
+        assert_eq!(settled_changes(&mut watcher), std::slice::from_ref(&name));
+
+        watcher.watch(set(&[&name]));
+        assert!(watcher.missing.contains(&name));
+        assert!(settled_changes(&mut watcher).is_empty());
+
+        fs::create_dir(&dir).unwrap();
+        watcher.watch(set(&[&name]));
+        assert!(!watcher.missing.contains(&name));
+        assert_eq!(settled_changes(&mut watcher), std::slice::from_ref(&name));
+
+        fs::write(dir.join("a"), "").unwrap();
+        assert_eq!(settled_changes(&mut watcher), [name]);
+    }
+}